@@ -1,12 +1,75 @@
+use clap::ValueEnum;
+
+// how colors are reduced to the single channel of the I and IA formats
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum IntensityMode {
+    Rec601,
+    #[default]
+    Rec709,
+    Average,
+    // the brightest of the red, green and blue channels
+    Max,
+    Red,
+    Green,
+    Blue,
+    // for art authored as white on transparent, like fonts
+    Alpha,
+}
+
+// the K0-K5 values of SetConvert, used to turn YUV16 texels into RGB
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct YUVCoefficients {
+    pub k0: i16,
+    pub k1: i16,
+    pub k2: i16,
+    pub k3: i16,
+    pub k4: i16,
+    pub k5: i16,
+}
+
+impl Default for YUVCoefficients {
+    // G_CV_K0 through G_CV_K5 from libultra
+    fn default() -> Self {
+        Self {
+            k0: 175,
+            k1: -43,
+            k2: -89,
+            k3: 222,
+            k4: 114,
+            k5: 42,
+        }
+    }
+}
+
+// widens a value of `bits` bits to 8 by repeating its bits into the empty low ones,
+// so the largest value maps to 255 and truncating back gives the original value
+#[inline]
+pub fn expand(value: u8, bits: u32) -> u8 {
+    let bits = bits as i32;
+    let mut expanded = 0u32;
+    let mut shift = 8 - bits;
+
+    while shift > -bits {
+        expanded |= match shift {
+            0.. => (value as u32) << shift,
+            _ => value as u32 >> -shift,
+        };
+        shift -= bits;
+    }
+
+    expanded as u8
+}
+
 pub struct R5G5B5A1 {}
 
 impl R5G5B5A1 {
+    // pixels with at least `alpha_threshold` alpha get the opaque bit
     #[inline]
-    pub fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> u16 {
+    pub fn from_rgba(r: u8, g: u8, b: u8, a: u8, alpha_threshold: u8) -> u16 {
         let r = (r / 8) as u16;
         let g = (g / 8) as u16;
         let b = (b / 8) as u16;
-        let a = (a / 255) as u16;
+        let a = (a >= alpha_threshold) as u16;
 
         (r << 11) | (g << 6) | (b << 1) | a
     }
@@ -18,7 +81,7 @@ impl R5G5B5A1 {
         let b = ((pixel & 0x003E) >> 1) as u8;
         let a = (pixel & 0x01) as u8;
 
-        vec![r * 8, g * 8, b * 8, a * 255]
+        vec![expand(r, 5), expand(g, 5), expand(b, 5), a * 255]
     }
 }
 
@@ -28,6 +91,75 @@ impl Intensity {
     #[inline]
     pub fn from_rgb(r: u8, g: u8, b: u8) -> u8 {
         // Seen on Stack Overflow: https://stackoverflow.com/questions/687261/converting-rgb-to-grayscale-intensity/689547#comment20506224_689547
-        (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) as u8
+        // rounded so grays keep their exact value
+        (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+    }
+
+    #[inline]
+    pub fn from_rgba(r: u8, g: u8, b: u8, a: u8, mode: IntensityMode) -> u8 {
+        match mode {
+            IntensityMode::Rec601 => {
+                (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8
+            }
+            IntensityMode::Rec709 => Intensity::from_rgb(r, g, b),
+            IntensityMode::Average => ((r as u16 + g as u16 + b as u16 + 1) / 3) as u8,
+            IntensityMode::Max => r.max(g).max(b),
+            IntensityMode::Red => r,
+            IntensityMode::Green => g,
+            IntensityMode::Blue => b,
+            IntensityMode::Alpha => a,
+        }
+    }
+}
+
+pub struct Yuv {}
+
+impl Yuv {
+    // the texture filter adds the K0-K3 weighted chroma to Y, then the combiner stretches
+    // the result out of video range with (TEXEL - K4) * K5 + TEXEL, like G_CC_YUV2RGB does
+    pub fn to_rgb(y: u8, u: u8, v: u8, coefficients: &YUVCoefficients) -> [u8; 3] {
+        let [k0, k1, k2, k3] = Yuv::chroma_weights(coefficients);
+        let (k4, k5) = (coefficients.k4 as i32, coefficients.k5 as i32);
+
+        let y = y as i32;
+        let u = u as i32 - 128;
+        let v = v as i32 - 128;
+
+        [
+            y + ((k0 * v + 0x80) >> 8),
+            y + ((k1 * u + k2 * v + 0x80) >> 8),
+            y + ((k3 * u + 0x80) >> 8),
+        ]
+        .map(|color| {
+            let color = color.clamp(0, 255);
+            (color + (((color - k4) * k5 + 0x80) >> 8)).clamp(0, 255) as u8
+        })
+    }
+
+    // inverts to_rgb for the same coefficients, returning (Y, U, V)
+    pub fn from_rgb(r: u8, g: u8, b: u8, coefficients: &YUVCoefficients) -> (u8, u8, u8) {
+        let [k0, k1, k2, k3] = Yuv::chroma_weights(coefficients).map(|k| k as f32 / 256.0);
+        let (k4, k5) = (coefficients.k4 as f32, coefficients.k5 as f32);
+
+        let [r, g, b] =
+            [r, g, b].map(|color| (color as f32 + k4 * k5 / 256.0) / (1.0 + k5 / 256.0));
+
+        let y = (g - k1 / k3 * b - k2 / k0 * r) / (1.0 - k1 / k3 - k2 / k0);
+        let u = (b - y) / k3 + 128.0;
+        let v = (r - y) / k0 + 128.0;
+
+        let [y, u, v] = [y, u, v].map(|value| value.round().clamp(0.0, 255.0) as u8);
+        (y, u, v)
+    }
+
+    // K0-K3 as the 1.8 fixed point multipliers the texture filter applies
+    fn chroma_weights(coefficients: &YUVCoefficients) -> [i32; 4] {
+        [
+            coefficients.k0,
+            coefficients.k1,
+            coefficients.k2,
+            coefficients.k3,
+        ]
+        .map(|k| 2 * k as i32 + 1)
     }
 }
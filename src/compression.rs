@@ -0,0 +1,269 @@
+pub mod mio0;
+pub mod yay0;
+pub mod yaz0;
+
+use crate::error::{Error, Result};
+
+// a step of an LZ77 parse, either a byte copied as is or a run of earlier output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    Literal(u8),
+    Copy { distance: usize, length: usize },
+}
+
+// decompresses data wrapped in any of the supported formats, anything else is returned as is
+pub fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    if data.starts_with(mio0::MAGIC) {
+        mio0::decode(&data)
+    } else if data.starts_with(yay0::MAGIC) {
+        yay0::decode(&data)
+    } else if data.starts_with(yaz0::MAGIC) {
+        yaz0::decode(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+// appends `count` bytes starting `distance` bytes back, the run can overlap what it writes
+fn copy(output: &mut Vec<u8>, distance: usize, count: usize) -> Result<()> {
+    if distance > output.len() {
+        return Err(Error::CorruptCompressedData);
+    }
+
+    for _ in 0..count {
+        output.push(output[output.len() - distance]);
+    }
+
+    Ok(())
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8> {
+    data.get(offset).copied().ok_or(Error::TruncatedData {
+        expected: offset + 1,
+        actual: data.len(),
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into().unwrap())),
+        None => Err(Error::TruncatedData {
+            expected: offset + 4,
+            actual: data.len(),
+        }),
+    }
+}
+
+// the literal stream of a MIO0 or Yay0 block, Yay0 also takes long lengths from it
+struct Literals<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Literals<'_> {
+    fn next(&mut self) -> Result<u8> {
+        self.position += 1;
+        read_u8(self.data, self.position - 1)
+    }
+}
+
+// MIO0 and Yay0 blocks keep three streams after their 16 byte header: a bitfield with one
+// bit per step (set for a literal), the 16-bit back references and the literal bytes. the
+// header holds the decompressed length and where the back references and literals start.
+// `count` turns the top nibble of a back reference into the number of bytes it copies
+fn decode_streams(
+    data: &[u8],
+    magic: &[u8; 4],
+    count: fn(u16, &mut Literals) -> Result<usize>,
+) -> Result<Vec<u8>> {
+    if !data.starts_with(magic) {
+        return Err(Error::CorruptCompressedData);
+    }
+
+    let length = read_u32(data, 4)? as usize;
+    let mut references = read_u32(data, 8)? as usize;
+    let mut literals = Literals {
+        data,
+        position: read_u32(data, 12)? as usize,
+    };
+    let mut layout = 16;
+
+    let mut output = Vec::new();
+    let mut flags = 0u32;
+    let mut remaining = 0;
+
+    while output.len() < length {
+        if remaining == 0 {
+            flags = read_u32(data, layout)?;
+            layout += 4;
+            remaining = 32;
+        }
+
+        let literal = flags & 0x8000_0000 != 0;
+        flags <<= 1;
+        remaining -= 1;
+
+        if literal {
+            output.push(literals.next()?);
+            continue;
+        }
+
+        let reference =
+            u16::from_be_bytes([read_u8(data, references)?, read_u8(data, references + 1)?]);
+        references += 2;
+
+        let count = count(reference >> 12, &mut literals)?;
+        let distance = (reference & 0x0FFF) as usize + 1;
+        copy(&mut output, distance, count)?;
+    }
+
+    output.truncate(length);
+    Ok(output)
+}
+
+// lays out the tokens of `data` as a MIO0 or Yay0 block, `nibble` gives the top nibble of
+// a back reference of the given length and a byte to add to the literal stream if needed
+fn encode_streams(
+    data: &[u8],
+    magic: &[u8; 4],
+    tokens: &[Token],
+    nibble: fn(usize) -> (usize, Option<u8>),
+) -> Vec<u8> {
+    let mut flags = vec![0u32; tokens.len().div_ceil(32)];
+    let mut references = Vec::new();
+    let mut literals = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::Literal(byte) => {
+                flags[index / 32] |= 0x8000_0000 >> (index % 32);
+                literals.push(byte);
+            }
+            Token::Copy { distance, length } => {
+                let (nibble, extra) = nibble(length);
+                let reference = (nibble << 12 | (distance - 1)) as u16;
+                references.extend_from_slice(&reference.to_be_bytes());
+                literals.extend(extra);
+            }
+        }
+    }
+
+    let references_offset = 16 + flags.len() * 4;
+    let literals_offset = references_offset + references.len();
+
+    let mut output = Vec::with_capacity(literals_offset + literals.len());
+    output.extend_from_slice(magic);
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(&(references_offset as u32).to_be_bytes());
+    output.extend_from_slice(&(literals_offset as u32).to_be_bytes());
+    output.extend(flags.iter().flat_map(|word| word.to_be_bytes()));
+    output.extend_from_slice(&references);
+    output.extend_from_slice(&literals);
+    output
+}
+
+const MIN_LENGTH: usize = 3;
+const HASH_BITS: u32 = 15;
+
+// greedy LZ77 parse over hash chains of positions starting with the same three bytes,
+// with one byte of lazy matching: a match is passed over for a literal when the one
+// starting at the next byte is longer
+pub(crate) fn tokenize(data: &[u8], window: usize, max_length: usize) -> Vec<Token> {
+    let mut matcher = Matcher::new(data, window, max_length);
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        match matcher.find(position) {
+            Some((distance, length))
+                if matcher
+                    .find(position + 1)
+                    .is_none_or(|(_, next)| next <= length) =>
+            {
+                tokens.push(Token::Copy { distance, length });
+                position += length;
+            }
+            _ => {
+                tokens.push(Token::Literal(data[position]));
+                position += 1;
+            }
+        }
+    }
+
+    tokens
+}
+
+struct Matcher<'a> {
+    data: &'a [u8],
+    window: usize,
+    max_length: usize,
+    // most recent position for every hash and the position before it with the same hash
+    head: Vec<usize>,
+    previous: Vec<usize>,
+    inserted: usize,
+}
+
+impl<'a> Matcher<'a> {
+    const NONE: usize = usize::MAX;
+
+    fn new(data: &'a [u8], window: usize, max_length: usize) -> Self {
+        Self {
+            data,
+            window,
+            max_length,
+            head: vec![Matcher::NONE; 1 << HASH_BITS],
+            previous: vec![Matcher::NONE; data.len()],
+            inserted: 0,
+        }
+    }
+
+    // longest earlier run within the window matching the bytes at `position`,
+    // as its distance back and length
+    fn find(&mut self, position: usize) -> Option<(usize, usize)> {
+        if position + MIN_LENGTH > self.data.len() {
+            return None;
+        }
+
+        while self.inserted < position {
+            self.insert(self.inserted);
+            self.inserted += 1;
+        }
+
+        let max_length = self.max_length.min(self.data.len() - position);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(position)];
+
+        while candidate != Matcher::NONE && position - candidate <= self.window {
+            let length = (0..max_length)
+                .take_while(|offset| self.data[candidate + offset] == self.data[position + offset])
+                .count();
+
+            if length >= MIN_LENGTH && best.is_none_or(|(_, best)| length > best) {
+                best = Some((position - candidate, length));
+                if length == max_length {
+                    break;
+                }
+            }
+
+            candidate = self.previous[candidate];
+        }
+
+        best
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_LENGTH > self.data.len() {
+            return;
+        }
+
+        let hash = self.hash(position);
+        self.previous[position] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.data[position..position + MIN_LENGTH];
+        let key = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        (key.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+}
@@ -0,0 +1,18 @@
+use super::{decode_streams, encode_streams};
+use crate::error::Result;
+
+pub const MAGIC: &[u8; 4] = b"MIO0";
+
+// back references reach up to 4096 bytes back and copy 3 to 18 bytes
+const WINDOW: usize = 0x1000;
+const MAX_LENGTH: usize = 18;
+
+// the top nibble of a back reference holds its length minus 3
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    decode_streams(data, MAGIC, |nibble, _| Ok(nibble as usize + 3))
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let tokens = super::tokenize(data, WINDOW, MAX_LENGTH);
+    encode_streams(data, MAGIC, &tokens, |length| (length - 3, None))
+}
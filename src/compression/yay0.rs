@@ -0,0 +1,25 @@
+use super::{decode_streams, encode_streams};
+use crate::error::Result;
+
+pub const MAGIC: &[u8; 4] = b"Yay0";
+
+// back references reach up to 4096 bytes back and copy 3 to 273 bytes
+const WINDOW: usize = 0x1000;
+const MAX_LENGTH: usize = 0x111;
+
+// laid out like MIO0, but the top nibble of a back reference holds its length minus 2.
+// a nibble of 0 takes the length minus 18 from the next byte of the literal stream instead
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    decode_streams(data, MAGIC, |nibble, literals| match nibble {
+        0 => Ok(literals.next()? as usize + 0x12),
+        nibble => Ok(nibble as usize + 2),
+    })
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let tokens = super::tokenize(data, WINDOW, MAX_LENGTH);
+    encode_streams(data, MAGIC, &tokens, |length| match length {
+        0x12.. => (0, Some((length - 0x12) as u8)),
+        _ => (length - 2, None),
+    })
+}
@@ -0,0 +1,93 @@
+use super::{copy, read_u32, read_u8, Token};
+use crate::error::{Error, Result};
+
+pub const MAGIC: &[u8; 4] = b"Yaz0";
+
+// back references reach up to 4096 bytes back and copy 3 to 273 bytes
+const WINDOW: usize = 0x1000;
+const MAX_LENGTH: usize = 0x111;
+
+// a single stream after the 16 byte header, where every group of 8 steps starts with a byte
+// of flags (set for a literal). back references are two bytes, or three when their length
+// doesn't fit in the top nibble and the third byte holds the length minus 18
+pub fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    if !data.starts_with(MAGIC) {
+        return Err(Error::CorruptCompressedData);
+    }
+
+    let length = read_u32(data, 4)? as usize;
+    let mut position = 16;
+
+    let mut output = Vec::new();
+    let mut flags = 0u8;
+    let mut remaining = 0;
+
+    while output.len() < length {
+        if remaining == 0 {
+            flags = read_u8(data, position)?;
+            position += 1;
+            remaining = 8;
+        }
+
+        let literal = flags & 0x80 != 0;
+        flags <<= 1;
+        remaining -= 1;
+
+        if literal {
+            output.push(read_u8(data, position)?);
+            position += 1;
+            continue;
+        }
+
+        let (high, low) = (read_u8(data, position)?, read_u8(data, position + 1)?);
+        position += 2;
+
+        let count = match high >> 4 {
+            0 => {
+                position += 1;
+                read_u8(data, position - 1)? as usize + 0x12
+            }
+            count => count as usize + 2,
+        };
+        let distance = ((high as usize & 0x0F) << 8 | low as usize) + 1;
+        copy(&mut output, distance, count)?;
+    }
+
+    output.truncate(length);
+    Ok(output)
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let tokens = super::tokenize(data, WINDOW, MAX_LENGTH);
+
+    let mut output = Vec::with_capacity(16 + data.len() + data.len() / 8 + 1);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(&[0; 8]);
+
+    for group in tokens.chunks(8) {
+        let flags = output.len();
+        output.push(0);
+
+        for (index, token) in group.iter().enumerate() {
+            match *token {
+                Token::Literal(byte) => {
+                    output[flags] |= 0x80 >> index;
+                    output.push(byte);
+                }
+                Token::Copy { distance, length } => {
+                    let distance = distance - 1;
+                    let nibble = if length >= 0x12 { 0 } else { length - 2 };
+                    output.push((nibble << 4 | distance >> 8) as u8);
+                    output.push(distance as u8);
+
+                    if nibble == 0 {
+                        output.push((length - 0x12) as u8);
+                    }
+                }
+            }
+        }
+    }
+
+    output
+}
@@ -0,0 +1,81 @@
+use crate::color::expand;
+use clap::ValueEnum;
+
+// how lossy conversions spread out the error of reducing colors to fewer bits
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Dither {
+    #[default]
+    None,
+    // ordered dither with the RDP's 4x4 magic square
+    Bayer,
+    FloydSteinberg,
+}
+
+// the 4x4 magic square the RDP adds to colors for its ordered dither
+pub const MAGIC_SQUARE: [[u8; 4]; 4] = [[0, 6, 1, 7], [4, 2, 5, 3], [3, 5, 2, 4], [7, 1, 6, 0]];
+
+// reduces a plane of 8-bit values to `bits` bits. without dithering values are truncated, the
+// ordered dither adds the magic square scaled to one step before truncating like the RDP does
+// and Floyd-Steinberg spreads the error of rounding to the nearest level onto its neighbours
+pub fn reduce(values: &[u8], width: u32, bits: u32, dither: Dither) -> Vec<u8> {
+    let shift = 8 - bits;
+    let width = width as usize;
+
+    match dither {
+        Dither::None => values.iter().map(|value| value >> shift).collect(),
+        Dither::Bayer => values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let offset = MAGIC_SQUARE[index / width % 4][index % width % 4] << shift >> 3;
+                value.saturating_add(offset) >> shift
+            })
+            .collect(),
+        Dither::FloydSteinberg => {
+            let max = (1u8 << bits) - 1;
+            let mut errors = vec![0f32; values.len()];
+
+            (0..values.len())
+                .map(|index| {
+                    let value = (values[index] as f32 + errors[index]).clamp(0.0, 255.0);
+
+                    let lower = (value as u8 >> shift).min(max);
+                    let upper = (lower + 1).min(max);
+                    let level = if value - expand(lower, bits) as f32
+                        <= expand(upper, bits) as f32 - value
+                    {
+                        lower
+                    } else {
+                        upper
+                    };
+
+                    let error = value - expand(level, bits) as f32;
+                    diffuse(&mut errors, index, width, error);
+                    level
+                })
+                .collect()
+        }
+    }
+}
+
+// Floyd-Steinberg weights: 7/16 to the right and 3/16, 5/16, 1/16 to the row below
+pub fn diffuse(errors: &mut [f32], index: usize, width: usize, error: f32) {
+    let x = index % width;
+    let below = index + width;
+
+    if x + 1 < width {
+        errors[index + 1] += error * 7.0 / 16.0;
+    }
+
+    if below < errors.len() {
+        if x > 0 {
+            errors[below - 1] += error * 3.0 / 16.0;
+        }
+
+        errors[below] += error * 5.0 / 16.0;
+
+        if x + 1 < width {
+            errors[below + 1] += error * 1.0 / 16.0;
+        }
+    }
+}
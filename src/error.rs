@@ -0,0 +1,114 @@
+use crate::image::n64::ImageFormat;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    // the input ended before all of the bytes the image needs
+    TruncatedData { expected: usize, actual: usize },
+    // the input holds more bytes than the image needs
+    TrailingData { expected: usize, actual: usize },
+    // the width and height don't describe the given image data
+    DimensionMismatch { width: u32, height: u32 },
+    // color indexed images can't be decoded without their TLUT
+    MissingTLUT(ImageFormat),
+    // an indexed png without a PLTE chunk
+    MissingPalette,
+    // TLUTs only make sense for CI4 and CI8 images
+    NotColorIndexed(ImageFormat),
+    // a pixel references an entry past the end of the TLUT
+    TLUTIndexOutOfRange { index: u8, entries: usize },
+    // a CI4 palette bank outside of the TLUT
+    PaletteBankOutOfRange { bank: u8, entries: usize },
+    // more colors than the TLUT or format can hold
+    TooManyColors { colors: usize, max: usize },
+    // a rom header that isn't 0x80371240 in any of the known byte orders
+    UnknownByteOrder([u8; 4]),
+    // the boot code doesn't match any of the known CICs, holds the crc32 of it
+    UnknownCic(u32),
+    // compressed data with the wrong magic or a back reference before its start
+    CorruptCompressedData,
+    Io(std::io::Error),
+    PngDecoding(png::DecodingError),
+    PngEncoding(png::EncodingError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TruncatedData { expected, actual } => write!(
+                f,
+                "truncated data: expected {} bytes but got {}",
+                expected, actual
+            ),
+            Error::TrailingData { expected, actual } => write!(
+                f,
+                "trailing data: expected {} bytes but got {}",
+                expected, actual
+            ),
+            Error::DimensionMismatch { width, height } => write!(
+                f,
+                "dimensions {}x{} don't match the image data",
+                width, height
+            ),
+            Error::MissingTLUT(format) => write!(f, "{:?} images require a TLUT", format),
+            Error::MissingPalette => write!(f, "indexed png is missing its palette"),
+            Error::NotColorIndexed(format) => write!(f, "{:?} images don't use a TLUT", format),
+            Error::TLUTIndexOutOfRange { index, entries } => write!(
+                f,
+                "TLUT index {} is out of range for a palette with {} entries",
+                index, entries
+            ),
+            Error::PaletteBankOutOfRange { bank, entries } => write!(
+                f,
+                "palette bank {} is out of range for a TLUT with {} entries",
+                bank, entries
+            ),
+            Error::TooManyColors { colors, max } => write!(
+                f,
+                "{} colors don't fit in a palette of at most {}",
+                colors, max
+            ),
+            Error::UnknownByteOrder(magic) => {
+                write!(f, "unknown rom byte order with header {:02X?}", magic)
+            }
+            Error::UnknownCic(crc) => {
+                write!(f, "unknown CIC for boot code with crc32 {:08X}", crc)
+            }
+            Error::CorruptCompressedData => write!(f, "corrupt compressed data"),
+            Error::Io(error) => write!(f, "i/o error: {}", error),
+            Error::PngDecoding(error) => write!(f, "png decoding error: {}", error),
+            Error::PngEncoding(error) => write!(f, "png encoding error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::PngDecoding(error) => Some(error),
+            Error::PngEncoding(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<png::DecodingError> for Error {
+    fn from(error: png::DecodingError) -> Self {
+        Error::PngDecoding(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::PngEncoding(error)
+    }
+}
@@ -1,10 +1,21 @@
-use crate::color::{Intensity, R5G5B5A1};
-use anyhow::Result;
+use crate::color::{expand, Intensity, Yuv, R5G5B5A1};
+use crate::compression;
+use crate::dither;
+use crate::error::{Error, Result};
+use crate::quantize::MedianCut;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use clap::ValueEnum;
-use png::{BitDepth, ColorType};
+use png::{ColorType, Transformations};
+use std::borrow::Cow;
 use std::io::{Read, Write};
 
+pub mod mipmap;
+pub mod tile;
+pub mod tmem;
+
+pub use crate::color::{IntensityMode, YUVCoefficients};
+pub use crate::dither::Dither;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
 pub enum ImageFormat {
     I4,
@@ -16,6 +27,7 @@ pub enum ImageFormat {
     CI8,
     RGBA16,
     RGBA32,
+    YUV16,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
@@ -27,12 +39,86 @@ pub enum ImageSize {
 }
 
 impl ImageSize {
-    pub fn tlut_size_in_bytes(&self) -> usize {
+    pub fn bits_per_pixel(&self) -> usize {
         match self {
-            ImageSize::S4B => 0x10,
-            ImageSize::S8B => 0x100,
-            ImageSize::S16B => 0x1000,
-            ImageSize::S32B => 0x10000,
+            ImageSize::S4B => 4,
+            ImageSize::S8B => 8,
+            ImageSize::S16B => 16,
+            ImageSize::S32B => 32,
+        }
+    }
+}
+
+impl ImageFormat {
+    pub fn size(&self) -> ImageSize {
+        match self {
+            ImageFormat::I4 | ImageFormat::IA4 | ImageFormat::CI4 => ImageSize::S4B,
+            ImageFormat::I8 | ImageFormat::IA8 | ImageFormat::CI8 => ImageSize::S8B,
+            ImageFormat::IA16 | ImageFormat::RGBA16 | ImageFormat::YUV16 => ImageSize::S16B,
+            ImageFormat::RGBA32 => ImageSize::S32B,
+        }
+    }
+
+    // bytes a single row takes up, rows of 4-bit images with an odd width
+    // are padded to a whole byte
+    pub fn bytes_per_row(&self, width: u32) -> usize {
+        (width as usize * self.size().bits_per_pixel()).div_ceil(8)
+    }
+
+    // bytes a texture of the given dimensions takes up, handy for slicing textures out of ROMs
+    pub fn bytes_for(&self, width: u32, height: u32) -> usize {
+        self.bytes_per_row(width) * height as usize
+    }
+
+    // number of TLUT entries a color indexed format can address
+    pub fn tlut_entries(&self) -> Option<usize> {
+        match self {
+            ImageFormat::CI4 => Some(16),
+            ImageFormat::CI8 => Some(256),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum TLUTType {
+    RGBA16,
+    IA16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EncodeOptions {
+    pub dither: Dither,
+    pub yuv_coefficients: YUVCoefficients,
+    pub intensity: IntensityMode,
+    // formats with a single bit of alpha keep pixels with at least this much alpha opaque
+    pub alpha_threshold: u8,
+    // multiply colors by their alpha before encoding them
+    pub premultiply: bool,
+    // give transparent pixels the color of their neighbours so bilinear filtering
+    // doesn't blend in whatever color they happened to have, usually black
+    pub bleed: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            dither: Dither::None,
+            yuv_coefficients: YUVCoefficients::default(),
+            intensity: IntensityMode::default(),
+            alpha_threshold: 128,
+            premultiply: false,
+            bleed: false,
+        }
+    }
+}
+
+impl EncodeOptions {
+    fn snap_alpha(&self, alpha: u8) -> u8 {
+        if alpha >= self.alpha_threshold {
+            255
+        } else {
+            0
         }
     }
 }
@@ -44,18 +130,26 @@ pub struct NativeImage {
     pub data: Vec<u8>,
 }
 
+#[derive(Clone)]
 pub struct PNGImage {
     data: Vec<u8>,
+    width: u32,
+    height: u32,
+    // either Rgba or Indexed, see PNGImage::read
     color_type: ColorType,
-    bit_depth: BitDepth,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
 }
 
 pub struct TLUT {
     pub data: Vec<u8>,
-    pub size: ImageSize,
+    pub tlut_type: TLUTType,
 }
 
 impl NativeImage {
+    // reads the whole input, which has to be exactly the size of the image once
+    // it's been decompressed if it was compressed. input that's already the right
+    // size is taken as is, even when it happens to start with a compression magic
     pub fn read<R: Read>(
         mut reader: R,
         format: ImageFormat,
@@ -65,15 +159,108 @@ impl NativeImage {
         let mut data = Vec::new();
         reader.read_to_end(&mut data)?;
 
-        Ok(Self {
+        let expected = format.bytes_for(width, height);
+        if data.len() != expected {
+            data = compression::decompress(data)?;
+        }
+
+        if data.len() > expected {
+            return Err(Error::TrailingData {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let image = Self {
             format,
             width,
             height,
             data,
+        };
+        image.check_size()?;
+
+        Ok(image)
+    }
+
+    // reads only the bytes the image needs, leaving any trailing data in the reader
+    pub fn read_prefix<R: Read>(
+        reader: R,
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let mut data = Vec::new();
+        reader
+            .take(format.bytes_for(width, height) as u64)
+            .read_to_end(&mut data)?;
+
+        let image = Self {
+            format,
+            width,
+            height,
+            data,
+        };
+        image.check_size()?;
+
+        Ok(image)
+    }
+
+    // textures loaded into TMEM with LoadBlock have the 32-bit words of every odd row
+    // swapped (64-bit halves for RGBA32), interleave applies that swap to a linear image
+    pub fn interleave(&self) -> Result<Self> {
+        self.swap_odd_lines()
+    }
+
+    // undoes the odd row swap of an interleaved image, giving back its linear layout
+    pub fn deinterleave(&self) -> Result<Self> {
+        self.swap_odd_lines()
+    }
+
+    // swapping the words of odd rows is its own inverse so it serves both directions,
+    // rows have to hold a whole number of swapped pairs
+    fn swap_odd_lines(&self) -> Result<Self> {
+        self.check_size()?;
+
+        let word_size = match self.format {
+            ImageFormat::RGBA32 => 8,
+            _ => 4,
+        };
+
+        let row_bytes = self.format.bytes_per_row(self.width);
+        if !row_bytes.is_multiple_of(word_size * 2) {
+            return Err(Error::DimensionMismatch {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let mut data = self.data.clone();
+        for row in data
+            .chunks_exact_mut(row_bytes.max(1))
+            .take(self.height as usize)
+            .skip(1)
+            .step_by(2)
+        {
+            for pair in row.chunks_exact_mut(word_size * 2) {
+                let (first, second) = pair.split_at_mut(word_size);
+                first.swap_with_slice(second);
+            }
+        }
+
+        Ok(Self {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            data,
         })
     }
 
-    pub fn decode(&self, tlut_color_table: Option<&[u8]>) -> Result<Vec<u8>> {
+    // decodes the image into RGBA8 pixels, color indexed images look their colors up in
+    // the TLUT where CI4 images use the 16 entries of the given palette bank. YUV16
+    // images are converted with the default coefficients, see decode_yuv16
+    pub fn decode(&self, tlut: Option<&TLUT>, palette: u8) -> Result<Vec<u8>> {
+        self.check_size()?;
+
         let mut data: Vec<u8> = Vec::new();
         let mut cursor = std::io::Cursor::new(&self.data);
 
@@ -98,51 +285,29 @@ impl NativeImage {
                 }
             }
             ImageFormat::CI4 => {
-                assert!(tlut_color_table.is_some());
+                let tlut = tlut.ok_or(Error::MissingTLUT(self.format))?;
+                let tlut_color_table = tlut.palette(self.format, palette)?;
 
-                for _y in 0..self.height {
-                    for _x in (0..self.width).step_by(2) {
-                        let byte = cursor.read_u8()?;
-                        data.append(&mut NativeImage::get_tlut_color(
-                            tlut_color_table.unwrap(),
-                            (byte >> 4) & 0x0F,
-                        ));
-                        data.append(&mut NativeImage::get_tlut_color(
-                            tlut_color_table.unwrap(),
-                            byte & 0x0F,
-                        ));
-                    }
+                for index in unpack_nibbles(&self.data, self.width, self.height) {
+                    data.append(&mut NativeImage::get_tlut_color(&tlut_color_table, index)?);
                 }
             }
             ImageFormat::CI8 => {
-                assert!(tlut_color_table.is_some());
+                let tlut = tlut.ok_or(Error::MissingTLUT(self.format))?;
+                let tlut_color_table = tlut.palette(self.format, palette)?;
 
                 for _y in 0..self.height {
                     for _x in 0..self.width {
                         let byte = cursor.read_u8()?;
-                        data.append(&mut NativeImage::get_tlut_color(
-                            tlut_color_table.unwrap(),
-                            byte,
-                        ));
+                        data.append(&mut NativeImage::get_tlut_color(&tlut_color_table, byte)?);
                     }
                 }
             }
             ImageFormat::IA4 => {
-                for y in 0..self.height {
-                    for x in (0..self.width).step_by(2) {
-                        let index = (y * self.width + x) / 2;
-                        let byte = self.data[index as usize];
-
-                        let source = (byte & 0xF0) >> 4;
-                        let grayscale = ((source & 0x0E) >> 1) * 32;
-                        let alpha = (source & 0x01) * 255;
-                        data.append(&mut vec![grayscale, grayscale, grayscale, alpha]);
-
-                        let source = byte & 0x0F;
-                        let grayscale = ((source & 0x0E) >> 1) * 32;
-                        let alpha = (source & 0x01) * 255;
-                        data.append(&mut vec![grayscale, grayscale, grayscale, alpha]);
-                    }
+                for source in unpack_nibbles(&self.data, self.width, self.height) {
+                    let grayscale = expand((source & 0x0E) >> 1, 3);
+                    let alpha = (source & 0x01) * 255;
+                    data.append(&mut vec![grayscale, grayscale, grayscale, alpha]);
                 }
             }
             ImageFormat::IA8 => {
@@ -151,8 +316,8 @@ impl NativeImage {
                         let index = (y * self.width + x) as usize;
                         let byte = self.data[index];
 
-                        let grayscale = byte & 0xF0;
-                        let alpha = (byte & 0x0F) << 4;
+                        let grayscale = expand(byte >> 4, 4);
+                        let alpha = expand(byte & 0x0F, 4);
 
                         data.append(&mut vec![grayscale, grayscale, grayscale, alpha])
                     }
@@ -169,17 +334,9 @@ impl NativeImage {
                 }
             }
             ImageFormat::I4 => {
-                for y in 0..self.height {
-                    for x in (0..self.width).step_by(2) {
-                        let index = (y * self.width + x) / 2;
-                        let byte = self.data[index as usize];
-
-                        let grayscale1 = byte & 0xF0;
-                        let grayscale2 = (byte & 0x0F) << 4;
-
-                        data.append(&mut vec![grayscale1, grayscale1, grayscale1, grayscale1]);
-                        data.append(&mut vec![grayscale2, grayscale2, grayscale2, grayscale2]);
-                    }
+                for source in unpack_nibbles(&self.data, self.width, self.height) {
+                    let grayscale = expand(source, 4);
+                    data.append(&mut vec![grayscale, grayscale, grayscale, grayscale]);
                 }
             }
             ImageFormat::I8 => {
@@ -192,26 +349,82 @@ impl NativeImage {
                     }
                 }
             }
+            ImageFormat::YUV16 => return self.decode_yuv16(&YUVCoefficients::default()),
         }
 
         Ok(data)
     }
 
-    fn get_tlut_color(tlut_table: &[u8], index: u8) -> Vec<u8> {
-        let r = tlut_table[(index * 4) as usize];
-        let g = tlut_table[((index * 4) + 1) as usize];
-        let b = tlut_table[((index * 4) + 2) as usize];
-        let a = tlut_table[((index * 4) + 3) as usize];
+    // YUV16 stores pairs of pixels as U Y0 V Y1, both pixels share the same chroma so
+    // the width has to be even. YUV has no alpha, every pixel comes out opaque
+    pub fn decode_yuv16(&self, coefficients: &YUVCoefficients) -> Result<Vec<u8>> {
+        self.check_size()?;
+
+        if !self.width.is_multiple_of(2) {
+            return Err(Error::DimensionMismatch {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let pixels = self.width as usize * self.height as usize;
+        let mut data = Vec::with_capacity(pixels * 4);
+
+        for pair in self.data[..pixels * 2].chunks_exact(4) {
+            let [u, y0, v, y1] = [pair[0], pair[1], pair[2], pair[3]];
+
+            for y in [y0, y1] {
+                data.extend_from_slice(&Yuv::to_rgb(y, u, v, coefficients));
+                data.push(0xFF);
+            }
+        }
+
+        Ok(data)
+    }
+
+    // makes sure the data covers every pixel so decoding never reads out of bounds
+    fn check_size(&self) -> Result<()> {
+        let expected = self.format.bytes_for(self.width, self.height);
+        if self.data.len() < expected {
+            return Err(Error::TruncatedData {
+                expected,
+                actual: self.data.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_tlut_indices(indices: &[u8], entries: usize) -> Result<()> {
+        match indices.iter().find(|index| **index as usize >= entries) {
+            Some(index) => Err(Error::TLUTIndexOutOfRange {
+                index: *index,
+                entries,
+            }),
+            None => Ok(()),
+        }
+    }
 
-        vec![r, g, b, a]
+    fn get_tlut_color(tlut_table: &[u8], index: u8) -> Result<Vec<u8>> {
+        let start = index as usize * 4;
+        let color = tlut_table
+            .get(start..start + 4)
+            .ok_or(Error::TLUTIndexOutOfRange {
+                index,
+                entries: tlut_table.len() / 4,
+            })?;
+
+        Ok(color.to_vec())
     }
 
-    pub fn as_png<W: Write>(&self, writer: &mut W) -> Result<()> {
+    pub fn as_png<W: Write>(&self, writer: &mut W, tlut: Option<&TLUT>, palette: u8) -> Result<()> {
+        self.check_size()?;
+
         let mut encoder = png::Encoder::new(writer, self.width, self.height);
 
         match self.format {
             ImageFormat::RGBA32 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -220,7 +433,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::RGBA16 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -229,7 +442,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::I4 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -238,7 +451,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::I8 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -247,7 +460,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::IA4 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -256,7 +469,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::IA8 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -264,7 +477,7 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::IA16 => {
-                let data = self.decode(None)?;
+                let data = self.decode(None, palette)?;
 
                 encoder.set_color(png::ColorType::Rgba);
                 encoder.set_depth(png::BitDepth::Eight);
@@ -272,24 +485,25 @@ impl NativeImage {
                 let mut writer = encoder.write_header()?;
                 writer.write_image_data(&data)?;
             }
-            ImageFormat::CI4 => {
-                let mut data = Vec::new();
-                let palette: Vec<u8> = vec![2 ^ 8; 16 * 16];
+            ImageFormat::YUV16 => {
+                let data = self.decode(None, palette)?;
 
-                for y in 0..self.height {
-                    for x in (0..self.width).step_by(2) {
-                        let index = (y * self.width + x) / 2;
-                        let byte = self.data[index as usize];
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
 
-                        let target_index = (byte & 0xF0) >> 4;
-                        data.push(target_index);
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(&data)?;
+            }
+            ImageFormat::CI4 => {
+                let tlut = tlut.ok_or(Error::MissingTLUT(self.format))?;
+                let colors = tlut.palette(self.format, palette)?;
+                let (png_palette, trns) = TLUT::as_png_palette(&colors);
+                let data = unpack_nibbles(&self.data, self.width, self.height);
 
-                        let target_index = byte & 0x0F;
-                        data.push(target_index);
-                    }
-                }
+                NativeImage::check_tlut_indices(&data, trns.len())?;
 
-                encoder.set_palette(palette);
+                encoder.set_palette(png_palette);
+                encoder.set_trns(trns);
                 encoder.set_color(png::ColorType::Indexed);
                 encoder.set_depth(png::BitDepth::Eight);
 
@@ -297,8 +511,10 @@ impl NativeImage {
                 writer.write_image_data(&data)?;
             }
             ImageFormat::CI8 => {
+                let tlut = tlut.ok_or(Error::MissingTLUT(self.format))?;
+                let colors = tlut.palette(self.format, palette)?;
+                let (png_palette, trns) = TLUT::as_png_palette(&colors);
                 let mut data = Vec::new();
-                let palette: Vec<u8> = vec![2 ^ 8; 16 * 16];
 
                 for y in 0..self.height {
                     for x in 0..self.width {
@@ -307,7 +523,10 @@ impl NativeImage {
                     }
                 }
 
-                encoder.set_palette(palette);
+                NativeImage::check_tlut_indices(&data, trns.len())?;
+
+                encoder.set_palette(png_palette);
+                encoder.set_trns(trns);
                 encoder.set_color(png::ColorType::Indexed);
                 encoder.set_depth(png::BitDepth::Eight);
 
@@ -321,142 +540,153 @@ impl NativeImage {
 }
 
 impl PNGImage {
+    // every png is normalized to 8-bit RGBA, except indexed ones which keep their palette
+    // and one 8-bit index per pixel so the color indexed formats can use them as they are
     pub fn read<R: Read>(reader: R) -> Result<Self> {
-        let decoder = png::Decoder::new(reader);
+        let mut decoder = png::Decoder::new(reader);
+        let indexed = decoder.read_header_info()?.color_type == ColorType::Indexed;
+        if !indexed {
+            // expands low bit depth grayscale, strips 16-bit samples and turns tRNS into alpha
+            decoder.set_transformations(Transformations::normalize_to_color8());
+        }
+
         let mut reader = decoder.read_info()?;
         let mut buf = vec![0; reader.output_buffer_size()];
         let info = reader.next_frame(&mut buf)?;
-        let input_bytes = &buf[..info.buffer_size()];
+        let samples = &buf[..info.buffer_size()];
+        let palette = reader.info().palette.as_ref().map(|p| p.to_vec());
+        let trns = reader.info().trns.as_ref().map(|t| t.to_vec());
+
+        let data = match info.color_type {
+            ColorType::Indexed => {
+                unpack_samples(samples, info.width, info.height, info.bit_depth as u32)
+            }
+            ColorType::Rgba => samples.to_vec(),
+            ColorType::Rgb => samples
+                .chunks_exact(3)
+                .flat_map(|chunk| [chunk[0], chunk[1], chunk[2], 0xFF])
+                .collect(),
+            ColorType::GrayscaleAlpha => samples
+                .chunks_exact(2)
+                .flat_map(|chunk| [chunk[0], chunk[0], chunk[0], chunk[1]])
+                .collect(),
+            ColorType::Grayscale => samples
+                .iter()
+                .flat_map(|value| [*value, *value, *value, 0xFF])
+                .collect(),
+        };
 
         Ok(Self {
-            data: input_bytes.to_vec(),
-            color_type: info.color_type,
-            bit_depth: info.bit_depth,
+            data,
+            width: info.width,
+            height: info.height,
+            color_type: match indexed {
+                true => ColorType::Indexed,
+                false => ColorType::Rgba,
+            },
+            palette,
+            trns,
         })
     }
 
-    pub fn as_native<W: Write>(&self, writer: &mut W, format: ImageFormat) -> Result<()> {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn as_native<W: Write>(
+        &self,
+        writer: &mut W,
+        format: ImageFormat,
+        options: &EncodeOptions,
+    ) -> Result<()> {
         match format {
-            ImageFormat::I4 => self.as_i4(writer),
-            ImageFormat::I8 => self.as_i8(writer),
-            ImageFormat::IA4 => self.as_ia4(writer),
-            ImageFormat::IA8 => self.as_ia8(writer),
-            ImageFormat::IA16 => self.as_ia16(writer),
-            ImageFormat::CI4 => self.as_ci4(writer),
-            ImageFormat::CI8 => self.as_ci8(writer),
-            ImageFormat::RGBA32 => self.as_rgba32(writer),
-            ImageFormat::RGBA16 => self.as_rgba16(writer),
+            ImageFormat::I4 => self.as_i4(writer, options),
+            ImageFormat::I8 => self.as_i8(writer, options),
+            ImageFormat::IA4 => self.as_ia4(writer, options),
+            ImageFormat::IA8 => self.as_ia8(writer, options),
+            ImageFormat::IA16 => self.as_ia16(writer, options),
+            ImageFormat::CI4 => self.as_ci4(writer, options),
+            ImageFormat::CI8 => self.as_ci8(writer, options),
+            ImageFormat::RGBA32 => self.as_rgba32(writer, options),
+            ImageFormat::RGBA16 => self.as_rgba16(writer, options),
+            ImageFormat::YUV16 => self.as_yuv16(writer, options),
         }
     }
 
-    pub fn as_rgba32<W: Write>(&self, writer: &mut W) -> Result<()> {
-        if self.color_type != ColorType::Rgba {
-            return Err(anyhow::anyhow!("Invalid color type"));
-        }
+    pub fn as_rgba32<W: Write>(&self, writer: &mut W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        writer.write_all(&image.as_rgba8()?)?;
+        Ok(())
+    }
+
+    pub fn as_rgba16<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, true)?;
+        let rgba = image.as_rgba8()?;
+
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            let values: Vec<u8> = rgba.iter().skip(channel).step_by(4).copied().collect();
+            dither::reduce(&values, image.width, 5, options.dither)
+        });
 
-        if self.bit_depth != BitDepth::Eight {
-            return Err(anyhow::anyhow!("Invalid bit depth"));
+        for (index, chunk) in rgba.chunks_exact(4).enumerate() {
+            let (r, g, b) = (r[index] as u16, g[index] as u16, b[index] as u16);
+            let a = (chunk[3] >= options.alpha_threshold) as u16;
+            writer.write_u16::<BigEndian>((r << 11) | (g << 6) | (b << 1) | a)?;
         }
 
-        writer.write_all(&self.data)?;
         Ok(())
     }
 
-    pub fn as_rgba16<W: Write>(&self, mut writer: W) -> Result<()> {
-        if self.color_type != ColorType::Rgba {
-            return Err(anyhow::anyhow!("Invalid color type"));
+    // neighbouring pixels are paired up and share the average of their chroma,
+    // so the width has to be even
+    pub fn as_yuv16<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        let coefficients = &options.yuv_coefficients;
+        if !image.width.is_multiple_of(2) {
+            return Err(Error::DimensionMismatch {
+                width: image.width,
+                height: image.height,
+            });
         }
 
-        if self.bit_depth != BitDepth::Eight {
-            return Err(anyhow::anyhow!("Invalid bit depth"));
-        }
+        for pair in image.as_rgba8()?.chunks_exact(8) {
+            let (y0, u0, v0) = Yuv::from_rgb(pair[0], pair[1], pair[2], coefficients);
+            let (y1, u1, v1) = Yuv::from_rgb(pair[4], pair[5], pair[6], coefficients);
 
-        self.data.chunks_exact(4).for_each(|chunk| {
-            let pixel = R5G5B5A1::from_rgba(chunk[0], chunk[1], chunk[2], chunk[3]);
-            writer.write_u16::<BigEndian>(pixel).unwrap();
-        });
+            let u = (u0 as u16 + u1 as u16).div_ceil(2) as u8;
+            let v = (v0 as u16 + v1 as u16).div_ceil(2) as u8;
+            writer.write_all(&[u, y0, v, y1])?;
+        }
 
         Ok(())
     }
 
-    pub fn as_i4<W: Write>(&self, mut writer: W) -> Result<()> {
-        match (self.color_type, self.bit_depth) {
-            (ColorType::Grayscale, BitDepth::Four) => {
-                writer.write_all(&self.data)?;
-            }
-            (ColorType::Grayscale, BitDepth::Eight) => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    let pixel = chunk[0] | chunk[1] >> 4;
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            (ColorType::GrayscaleAlpha, BitDepth::Eight) => {
-                self.data.chunks_exact(4).for_each(|chunk| {
-                    let pixel = chunk[0] | chunk[2] >> 4;
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            (ColorType::Rgba, BitDepth::Eight) => {
-                self.data.chunks_exact(8).for_each(|chunk| {
-                    let i1 = Intensity::from_rgb(chunk[0], chunk[1], chunk[2]);
-                    let i2 = Intensity::from_rgb(chunk[0], chunk[1], chunk[2]);
-                    let pixel = i1 | i2 >> 4;
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            (ColorType::Rgb, BitDepth::Eight) => {
-                self.data.chunks_exact(6).for_each(|chunk| {
-                    let i1 = Intensity::from_rgb(chunk[0], chunk[1], chunk[2]);
-                    let i2 = Intensity::from_rgb(chunk[3], chunk[4], chunk[5]);
-                    let pixel = i1 | i2 >> 4;
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!(
-                    "Invalid color type or bit depth: {:?}",
-                    case
-                ));
-            }
-        }
-
+    pub fn as_i4<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        let intensities: Vec<u8> = image
+            .as_grayscale_alpha(options.intensity)?
+            .iter()
+            .step_by(2)
+            .copied()
+            .collect();
+        let intensities = dither::reduce(&intensities, image.width, 4, options.dither);
+
+        writer.write_all(&pack_nibbles(&intensities, image.width, image.height)?)?;
         Ok(())
     }
 
-    pub fn as_i8<W: Write>(&self, mut writer: W) -> Result<()> {
-        match (self.color_type, self.bit_depth) {
-            (ColorType::Grayscale, BitDepth::Eight) => {
-                writer.write_all(&self.data)?;
-            }
-            (ColorType::Grayscale, BitDepth::Four) => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    let pixel = chunk[0] << 4 | chunk[1];
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            (ColorType::GrayscaleAlpha, BitDepth::Eight) => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    writer.write_u8(chunk[0]).unwrap();
-                });
-            }
-            (ColorType::Rgba, BitDepth::Eight) => {
-                self.data.chunks_exact(4).for_each(|chunk| {
-                    let intensity = Intensity::from_rgb(chunk[0], chunk[1], chunk[2]);
-                    writer.write_u8(intensity).unwrap();
-                });
-            }
-            (ColorType::Rgb, BitDepth::Eight) => {
-                self.data.chunks_exact(3).for_each(|chunk| {
-                    let intensity = Intensity::from_rgb(chunk[0], chunk[1], chunk[2]);
-                    writer.write_u8(intensity).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!(
-                    "Invalid color type or bit depth: {:?}",
-                    case
-                ));
-            }
+    pub fn as_i8<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        for intensity in image
+            .as_grayscale_alpha(options.intensity)?
+            .iter()
+            .step_by(2)
+        {
+            writer.write_u8(*intensity)?;
         }
 
         Ok(())
@@ -464,126 +694,524 @@ impl PNGImage {
 
     // ia4 consists of 4 bits: 3 bits are the intensity and one bit for the alpha
     // in our case we'll fit two ia4 pixels into one byte
-    pub fn as_ia4<W: Write>(&self, mut writer: W) -> Result<()> {
-        match (self.color_type, self.bit_depth) {
-            (ColorType::GrayscaleAlpha, BitDepth::Eight) => {
-                self.data.chunks_exact(4).for_each(|chunk| {
-                    let high = (chunk[0] >> 5) << 1 | (chunk[1] > 127) as u8;
-                    let low = (chunk[2] >> 5) << 1 | (chunk[3] > 127) as u8;
-
-                    writer.write_u8(high << 4 | low).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!(
-                    "Invalid color type or bit depth: {:?}",
-                    case
-                ));
-            }
+    pub fn as_ia4<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, true)?;
+        let samples = image.as_grayscale_alpha(options.intensity)?;
+        let intensities: Vec<u8> = samples.iter().step_by(2).copied().collect();
+        let intensities = dither::reduce(&intensities, image.width, 3, options.dither);
+        let pixels: Vec<u8> = intensities
+            .iter()
+            .zip(samples.chunks_exact(2))
+            .map(|(intensity, chunk)| intensity << 1 | (chunk[1] >= options.alpha_threshold) as u8)
+            .collect();
+
+        writer.write_all(&pack_nibbles(&pixels, image.width, image.height)?)?;
+        Ok(())
+    }
+
+    // ia8 consists of 8 bits: 4 bits are the intensity and the other 4 bits for the alpha,
+    // both get dithered on their own
+    pub fn as_ia8<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        let samples = image.as_grayscale_alpha(options.intensity)?;
+        let [intensities, alphas] = [0, 1].map(|channel| {
+            let values: Vec<u8> = samples.iter().skip(channel).step_by(2).copied().collect();
+            dither::reduce(&values, image.width, 4, options.dither)
+        });
+
+        for (intensity, alpha) in intensities.iter().zip(&alphas) {
+            writer.write_u8(intensity << 4 | alpha)?;
         }
 
         Ok(())
     }
 
-    // ia8 consists of 8 bits: 4 bits are the intensity and the other 4 bits for the alpha
-    pub fn as_ia8<W: Write>(&self, mut writer: W) -> Result<()> {
-        match (self.color_type, self.bit_depth) {
-            (ColorType::GrayscaleAlpha, BitDepth::Eight) => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    writer.write_u8(chunk[0] | chunk[1] >> 4).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!(
-                    "Invalid color type or bit depth: {:?}",
-                    case
-                ));
-            }
+    pub fn as_ia16<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        let image = self.prepared(options, false)?;
+        writer.write_all(&image.as_grayscale_alpha(options.intensity)?)?;
+        Ok(())
+    }
+
+    pub fn as_ci4<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        if self.color_type != ColorType::Indexed {
+            let (indices, _) = self.quantize(ImageFormat::CI4, TLUTType::RGBA16, options)?;
+            writer.write_all(&pack_nibbles(&indices, self.width, self.height)?)?;
+
+            return Ok(());
+        }
+
+        if let Some(index) = self.data.iter().max().filter(|index| **index > 0x0F) {
+            return Err(Error::TooManyColors {
+                colors: *index as usize + 1,
+                max: 16,
+            });
         }
 
+        writer.write_all(&pack_nibbles(&self.data, self.width, self.height)?)?;
         Ok(())
     }
 
-    pub fn as_ia16<W: Write>(&self, mut writer: W) -> Result<()> {
-        match (self.color_type, self.bit_depth) {
-            (ColorType::GrayscaleAlpha, BitDepth::Eight) => {
-                writer.write_all(&self.data)?;
-            }
-            case => {
-                return Err(anyhow::anyhow!(
-                    "Invalid color type or bit depth: {:?}",
-                    case
-                ));
-            }
+    pub fn as_ci8<W: Write>(&self, mut writer: W, options: &EncodeOptions) -> Result<()> {
+        if self.color_type != ColorType::Indexed {
+            let (indices, _) = self.quantize(ImageFormat::CI8, TLUTType::RGBA16, options)?;
+            writer.write_all(&indices)?;
+
+            return Ok(());
         }
 
+        writer.write_all(&self.data)?;
         Ok(())
     }
 
-    pub fn as_ci4<W: Write>(&self, mut writer: W) -> Result<()> {
+    // converts the PLTE and tRNS chunks of an indexed png into an RGBA16 or IA16 TLUT
+    // for the given color indexed format, the TLUT holds only as many entries as the
+    // palette does. non indexed pngs are quantized down to the colors the format can index
+    pub fn as_tlut(
+        &self,
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        options: &EncodeOptions,
+    ) -> Result<TLUT> {
         if self.color_type != ColorType::Indexed {
-            return Err(anyhow::anyhow!("Invalid color type: {:?}", self.color_type));
+            let (_, tlut) = self.quantize(format, tlut_type, options)?;
+            return Ok(tlut);
         }
 
-        match self.bit_depth {
-            BitDepth::Four => {
-                writer.write_all(&self.data)?;
-            }
-            BitDepth::Eight => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    let pixel = chunk[0] << 4 | chunk[1];
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!("Invalid bit depth: {:?}", case));
-            }
+        let palette = self.palette.as_ref().ok_or(Error::MissingPalette)?;
+
+        let pixels: Vec<u16> = palette
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(index, color)| {
+                let alpha = self
+                    .trns
+                    .as_ref()
+                    .and_then(|trns| trns.get(index).copied())
+                    .unwrap_or(255);
+
+                let [r, g, b] =
+                    [color[0], color[1], color[2]].map(|value| match options.premultiply {
+                        true => premultiply(value, alpha),
+                        false => value,
+                    });
+                TLUT::encode_color(tlut_type, [r, g, b, alpha], options)
+            })
+            .collect();
+
+        TLUT::from_pixels(&pixels, format, tlut_type)
+    }
+
+    // encodes a color indexed format together with the TLUT its indices point into. indexed
+    // pngs keep their own palette, anything else is quantized once for both
+    pub fn as_indexed(
+        &self,
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<u8>, TLUT)> {
+        if self.color_type == ColorType::Indexed {
+            let mut data = Vec::new();
+            self.as_native(&mut data, format, options)?;
+            return Ok((data, self.as_tlut(format, tlut_type, options)?));
         }
 
-        Ok(())
+        let (indices, tlut) = self.quantize(format, tlut_type, options)?;
+        let data = match format {
+            ImageFormat::CI4 => pack_nibbles(&indices, self.width, self.height)?,
+            _ => indices,
+        };
+
+        Ok((data, tlut))
     }
 
-    pub fn as_ci8<W: Write>(&self, mut writer: W) -> Result<()> {
-        if self.color_type != ColorType::Indexed {
-            return Err(anyhow::anyhow!("Invalid color type: {:?}", self.color_type));
+    // reduces the image to the 16 (CI4) or 256 (CI8) colors of a TLUT using median cut,
+    // returning the per pixel palette indices alongside the TLUT. colors are always
+    // picked in RGBA16 space so the indices don't depend on the TLUT type, dithering
+    // only changes which entry each pixel picks and never the TLUT itself.
+    pub fn quantize(
+        &self,
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<u8>, TLUT)> {
+        let max_entries = format
+            .tlut_entries()
+            .ok_or(Error::NotColorIndexed(format))?;
+        let rgba = self.as_snapped_rgba8(options)?;
+        let quantized = MedianCut::quantize(&rgba, self.width, max_entries, options.dither);
+        let tlut = TLUT::from_rgba16(&quantized.palette, format, tlut_type, options)?;
+
+        Ok((quantized.indices, tlut))
+    }
+
+    // applies the premultiply and bleed policies to a copy of the image. formats with a single
+    // bit of alpha drop every pixel under the threshold, so those all get their color bled
+    // instead of only the fully transparent ones
+    fn prepared(&self, options: &EncodeOptions, one_bit_alpha: bool) -> Result<Cow<'_, PNGImage>> {
+        if !options.premultiply && !options.bleed {
+            return Ok(Cow::Borrowed(self));
         }
 
-        match self.bit_depth {
-            BitDepth::Eight => {
-                writer.write_all(&self.data)?;
-            }
-            BitDepth::Four => {
-                self.data.chunks_exact(2).for_each(|chunk| {
-                    let pixel = chunk[0] << 4 | chunk[1];
-                    writer.write_u8(pixel).unwrap();
-                });
-            }
-            case => {
-                return Err(anyhow::anyhow!("Invalid bit depth: {:?}", case));
+        let mut data = self.as_rgba8()?;
+        if options.bleed {
+            let cutoff = if one_bit_alpha {
+                options.alpha_threshold
+            } else {
+                1
+            };
+            bleed(&mut data, self.width, 4, cutoff);
+        }
+
+        if options.premultiply {
+            for pixel in data.chunks_exact_mut(4) {
+                for index in 0..3 {
+                    pixel[index] = premultiply(pixel[index], pixel[3]);
+                }
             }
         }
 
-        Ok(())
+        Ok(Cow::Owned(PNGImage {
+            data,
+            width: self.width,
+            height: self.height,
+            color_type: ColorType::Rgba,
+            palette: None,
+            trns: None,
+        }))
+    }
+
+    // RGBA8 pixels with the alpha policies applied and alpha snapped to fully opaque or
+    // transparent, ready to be reduced to the colors of an RGBA16 TLUT
+    fn as_snapped_rgba8(&self, options: &EncodeOptions) -> Result<Vec<u8>> {
+        let mut rgba = self.prepared(options, true)?.as_rgba8()?;
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = options.snap_alpha(pixel[3]);
+        }
+
+        Ok(rgba)
+    }
+
+    // intensity and alpha pairs for the I and IA formats
+    fn as_grayscale_alpha(&self, mode: IntensityMode) -> Result<Vec<u8>> {
+        Ok(self
+            .as_rgba8()?
+            .chunks_exact(4)
+            .flat_map(|chunk| {
+                let intensity = Intensity::from_rgba(chunk[0], chunk[1], chunk[2], chunk[3], mode);
+                [intensity, chunk[3]]
+            })
+            .collect())
+    }
+
+    // indexed pngs are looked up in their palette, with tRNS giving the alpha of each entry
+    fn as_rgba8(&self) -> Result<Vec<u8>> {
+        if self.color_type != ColorType::Indexed {
+            return Ok(self.data.clone());
+        }
+
+        let palette = self.palette.as_ref().ok_or(Error::MissingPalette)?;
+        let entries = palette.len() / 3;
+
+        self.data
+            .iter()
+            .map(|index| {
+                let entry = *index as usize;
+                if entry >= entries {
+                    return Err(Error::TLUTIndexOutOfRange {
+                        index: *index,
+                        entries,
+                    });
+                }
+
+                let alpha = self
+                    .trns
+                    .as_ref()
+                    .and_then(|trns| trns.get(entry).copied())
+                    .unwrap_or(255);
+                let color = &palette[entry * 3..entry * 3 + 3];
+                Ok([color[0], color[1], color[2], alpha])
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|pixels| pixels.concat())
     }
 }
 
 impl TLUT {
-    pub fn read<R: Read>(mut reader: R, size: ImageSize) -> Result<Self> {
+    // the RDP keeps up to 256 entries in the upper half of TMEM,
+    // which CI4 textures address as 16 banks of 16 entries
+    pub const MAX_ENTRIES: usize = 256;
+    pub const BANK_ENTRIES: usize = 16;
+
+    pub fn read<R: Read>(mut reader: R, tlut_type: TLUTType) -> Result<Self> {
         let mut data = Vec::new();
         reader.read_to_end(&mut data)?;
 
-        Ok(Self { size, data })
+        // a trailing odd byte is an entry that got cut off
+        if !data.len().is_multiple_of(2) {
+            return Err(Error::TruncatedData {
+                expected: data.len() + 1,
+                actual: data.len(),
+            });
+        }
+
+        if data.len() / 2 > TLUT::MAX_ENTRIES {
+            return Err(Error::TooManyColors {
+                colors: data.len() / 2,
+                max: TLUT::MAX_ENTRIES,
+            });
+        }
+
+        Ok(Self { data, tlut_type })
+    }
+
+    pub fn entries(&self) -> usize {
+        self.data.len() / 2
+    }
+
+    // index of the first entry of one of the 16 CI4 palette banks
+    pub fn bank_offset(bank: u8) -> Result<usize> {
+        if bank as usize >= TLUT::MAX_ENTRIES / TLUT::BANK_ENTRIES {
+            return Err(Error::PaletteBankOutOfRange {
+                bank,
+                entries: TLUT::MAX_ENTRIES,
+            });
+        }
+
+        Ok(bank as usize * TLUT::BANK_ENTRIES)
+    }
+
+    // RGBA16 entries keep pixels at or above the alpha threshold opaque, IA16 keeps all of it
+    fn encode_color(tlut_type: TLUTType, color: [u8; 4], options: &EncodeOptions) -> u16 {
+        let [r, g, b, a] = color;
+        match tlut_type {
+            TLUTType::RGBA16 => R5G5B5A1::from_rgba(r, g, b, a, options.alpha_threshold),
+            TLUTType::IA16 => {
+                (Intensity::from_rgba(r, g, b, a, options.intensity) as u16) << 8 | a as u16
+            }
+        }
+    }
+
+    fn from_pixels(pixels: &[u16], format: ImageFormat, tlut_type: TLUTType) -> Result<Self> {
+        let max_entries = format
+            .tlut_entries()
+            .ok_or(Error::NotColorIndexed(format))?;
+
+        if pixels.len() > max_entries {
+            return Err(Error::TooManyColors {
+                colors: pixels.len(),
+                max: max_entries,
+            });
+        }
+
+        let mut data = Vec::with_capacity(pixels.len() * 2);
+        for pixel in pixels {
+            data.write_u16::<BigEndian>(*pixel)?;
+        }
+
+        Ok(Self { data, tlut_type })
+    }
+
+    // builds a TLUT of the given type out of RGBA16 colors, like the ones quantization picks
+    fn from_rgba16(
+        colors: &[u16],
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        options: &EncodeOptions,
+    ) -> Result<Self> {
+        let pixels: Vec<u16> = colors
+            .iter()
+            .map(|pixel| {
+                let color = R5G5B5A1::to_rgba(*pixel);
+                TLUT::encode_color(tlut_type, [color[0], color[1], color[2], color[3]], options)
+            })
+            .collect();
+
+        TLUT::from_pixels(&pixels, format, tlut_type)
     }
 
     pub fn decode(&self) -> Result<Vec<u8>> {
         let mut decoded = Vec::new();
-        let mut cursor = std::io::Cursor::new(&self.data);
 
-        for _i in 0..(self.size.tlut_size_in_bytes()) {
-            let pixel = cursor.read_u16::<BigEndian>()?;
-            decoded.append(&mut R5G5B5A1::to_rgba(pixel));
+        for entry in self.data.chunks_exact(2) {
+            let pixel = u16::from_be_bytes([entry[0], entry[1]]);
+
+            match self.tlut_type {
+                TLUTType::RGBA16 => decoded.append(&mut R5G5B5A1::to_rgba(pixel)),
+                TLUTType::IA16 => {
+                    let intensity = (pixel >> 8) as u8;
+                    let alpha = pixel as u8;
+                    decoded.append(&mut vec![intensity, intensity, intensity, alpha]);
+                }
+            }
         }
 
         Ok(decoded)
     }
+
+    // decodes the entries a color indexed format can address, CI4 images only see the
+    // 16 entries of the given bank while CI8 images ignore it and see the whole TLUT.
+    // partial TLUTs decode just the entries they contain.
+    pub fn palette(&self, format: ImageFormat, bank: u8) -> Result<Vec<u8>> {
+        let count = format
+            .tlut_entries()
+            .ok_or(Error::NotColorIndexed(format))?;
+
+        let offset = match format {
+            ImageFormat::CI4 => TLUT::bank_offset(bank)?,
+            _ => 0,
+        };
+
+        if offset >= self.entries() {
+            return Err(Error::PaletteBankOutOfRange {
+                bank,
+                entries: self.entries(),
+            });
+        }
+
+        let end = (offset + count).min(self.entries());
+        let decoded = self.decode()?;
+
+        Ok(decoded[offset * 4..end * 4].to_vec())
+    }
+
+    // splits decoded RGBA entries into the RGB triplets of a PLTE chunk
+    // and the per-entry alpha values of a tRNS chunk
+    fn as_png_palette(colors: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut palette = Vec::with_capacity(colors.len() / 4 * 3);
+        let mut trns = Vec::with_capacity(colors.len() / 4);
+
+        for color in colors.chunks_exact(4) {
+            palette.extend_from_slice(&color[..3]);
+            trns.push(color[3]);
+        }
+
+        (palette, trns)
+    }
+}
+
+// packs one 4-bit value per pixel into rows of two pixels per byte, rows with an odd
+// width end in a byte whose low nibble is left empty
+fn pack_nibbles(values: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    if values.len() != width as usize * height as usize {
+        return Err(Error::DimensionMismatch { width, height });
+    }
+
+    let mut packed = Vec::with_capacity(ImageFormat::I4.bytes_for(width, height));
+    for row in values.chunks_exact(width.max(1) as usize) {
+        for pair in row.chunks(2) {
+            let low = pair.get(1).copied().unwrap_or(0);
+            packed.push((pair[0] & 0x0F) << 4 | (low & 0x0F));
+        }
+    }
+
+    Ok(packed)
+}
+
+// splits rows of two 4-bit values per byte into one value per pixel, each row starts
+// on a new byte so rows with an odd width skip the low nibble of their last byte
+fn unpack_nibbles(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row_bytes = ImageFormat::I4.bytes_per_row(width);
+    let mut values = Vec::with_capacity(width as usize * height as usize);
+
+    for row in data.chunks(row_bytes.max(1)).take(height as usize) {
+        for x in 0..width as usize {
+            let byte = row[x / 2];
+            values.push(if x % 2 == 0 { byte >> 4 } else { byte & 0x0F });
+        }
+    }
+
+    values
+}
+
+// splits rows of 1, 2 or 4-bit samples into a byte each, rows start on a new byte
+fn unpack_samples(data: &[u8], width: u32, height: u32, bits: u32) -> Vec<u8> {
+    if bits == 8 {
+        return data.to_vec();
+    }
+
+    let row_bytes = (width as usize * bits as usize).div_ceil(8);
+    let mask = (1u8 << bits) - 1;
+    let mut values = Vec::with_capacity(width as usize * height as usize);
+
+    for row in data.chunks(row_bytes.max(1)).take(height as usize) {
+        for x in 0..width as usize {
+            let bit = x * bits as usize;
+            let shift = 8 - bits as usize - bit % 8;
+            values.push(row[bit / 8] >> shift & mask);
+        }
+    }
+
+    values
+}
+
+fn premultiply(value: u8, alpha: u8) -> u8 {
+    ((value as u32 * alpha as u32 + 127) / 255) as u8
+}
+
+// fills in the color of pixels with less alpha than `cutoff` with the average of their already
+// filled neighbours, growing outwards from the opaque pixels one ring at a time. each ring is
+// found from the neighbours of the previous one, so every pixel is only visited once
+fn bleed(data: &mut [u8], width: u32, channels: usize, cutoff: u8) {
+    let width = width as usize;
+    let pixels = data.len() / channels;
+    let colors = channels - 1;
+
+    let mut filled: Vec<bool> = data
+        .chunks_exact(channels)
+        .map(|pixel| pixel[colors] >= cutoff)
+        .collect();
+    if width == 0 {
+        return;
+    }
+
+    let height = pixels / width;
+    let neighbours = |index: usize| {
+        let (x, y) = (index % width, index / width);
+        (y.saturating_sub(1)..=(y + 1).min(height - 1)).flat_map(move |ny| {
+            (x.saturating_sub(1)..=(x + 1).min(width - 1)).map(move |nx| ny * width + nx)
+        })
+    };
+
+    let mut queued = filled.clone();
+    let mut ring: Vec<usize> = (0..pixels).filter(|index| filled[*index]).collect();
+
+    while !ring.is_empty() {
+        let mut next = Vec::new();
+        for index in ring {
+            for neighbour in neighbours(index) {
+                if !queued[neighbour] {
+                    queued[neighbour] = true;
+                    next.push(neighbour);
+                }
+            }
+        }
+
+        let colored: Vec<(usize, Vec<u8>)> = next
+            .iter()
+            .map(|index| {
+                let mut sums = vec![0u32; colors];
+                let mut count = 0;
+
+                for neighbour in neighbours(*index).filter(|neighbour| filled[*neighbour]) {
+                    count += 1;
+                    for (sum, value) in sums.iter_mut().zip(&data[neighbour * channels..]) {
+                        *sum += *value as u32;
+                    }
+                }
+
+                let color = sums
+                    .iter()
+                    .map(|sum| ((sum + count / 2) / count) as u8)
+                    .collect();
+                (*index, color)
+            })
+            .collect();
+
+        for (index, color) in colored {
+            data[index * channels..index * channels + colors].copy_from_slice(&color);
+            filled[index] = true;
+        }
+
+        ring = next;
+    }
 }
@@ -0,0 +1,206 @@
+use super::tmem::TMEM;
+use super::{pack_nibbles, EncodeOptions, ImageFormat, PNGImage, TLUTType, TLUT};
+use crate::error::Result;
+use crate::quantize::MedianCut;
+use png::ColorType;
+
+// one level of a mipmap chain, with its rows padded to whole 64-bit words
+pub struct MipmapLevel {
+    pub width: u32,
+    pub height: u32,
+    // row stride and TMEM address in 64-bit words, as SetTile expects them
+    pub line: u16,
+    pub tmem: u16,
+    pub data: Vec<u8>,
+}
+
+pub struct Mipmap {
+    pub format: ImageFormat,
+    pub levels: Vec<MipmapLevel>,
+    // CI4 and CI8 chains share a single TLUT between all of their levels
+    pub tlut: Option<TLUT>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MipmapBudget {
+    // bytes used by every level, RGBA32 and YUV16 count both of their halves
+    pub bytes: usize,
+    pub available: usize,
+    pub fits: bool,
+}
+
+impl Mipmap {
+    // the RDP only has 8 tile descriptors to point at levels
+    pub const MAX_LEVELS: usize = 8;
+
+    // builds a chain of up to `levels` levels (clamped to 1..=MAX_LEVELS), halving the image
+    // with a box filter until it gets down to 1x1. YUV16 pairs up pixels, so its chain stops
+    // before a level would get an odd width. levels are packed one after another the way a
+    // single LoadBlock puts them in TMEM for trilinear filtering
+    pub fn from_png(
+        image: &PNGImage,
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        levels: usize,
+        options: &EncodeOptions,
+    ) -> Result<Self> {
+        let levels = levels.clamp(1, Mipmap::MAX_LEVELS);
+
+        let mut downsampled: Vec<PNGImage> = Vec::new();
+        while downsampled.len() + 1 < levels {
+            let previous = downsampled.last().unwrap_or(image);
+            if previous.width == 1 && previous.height == 1 {
+                break;
+            }
+
+            let odd = !(previous.width / 2).max(1).is_multiple_of(2);
+            if format == ImageFormat::YUV16 && odd {
+                break;
+            }
+
+            let next = Mipmap::downsample(previous)?;
+            downsampled.push(next);
+        }
+
+        let images: Vec<&PNGImage> = std::iter::once(image).chain(&downsampled).collect();
+        let (natives, tlut) = match format.tlut_entries() {
+            Some(entries) => {
+                let (natives, tlut) =
+                    Mipmap::quantize(&images, format, tlut_type, entries, options)?;
+                (natives, Some(tlut))
+            }
+            None => {
+                let natives = images
+                    .iter()
+                    .map(|image| {
+                        let mut data = Vec::new();
+                        image.as_native(&mut data, format, options)?;
+                        Ok(data)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (natives, None)
+            }
+        };
+
+        let mut tmem = 0usize;
+        let mut levels = Vec::with_capacity(images.len());
+        for (image, data) in images.iter().zip(natives) {
+            let line = TMEM::line(format, image.width);
+            let row = format.bytes_per_row(image.width);
+            let padded = TMEM::row_bytes(format, line);
+
+            let data = data
+                .chunks_exact(row)
+                .flat_map(|row| {
+                    let padding = std::iter::repeat_n(0, padded - row.len());
+                    row.iter().copied().chain(padding)
+                })
+                .collect();
+
+            levels.push(MipmapLevel {
+                width: image.width,
+                height: image.height,
+                line,
+                tmem: tmem as u16,
+                data,
+            });
+            tmem += line as usize * image.height as usize;
+        }
+
+        Ok(Self {
+            format,
+            levels,
+            tlut,
+        })
+    }
+
+    // every level back to back, ready to be loaded with a single LoadBlock
+    pub fn data(&self) -> Vec<u8> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.data.iter().copied())
+            .collect()
+    }
+
+    // TMEM usage of the whole chain, CI chains only get the lower half
+    // since their TLUT has to be loaded alongside them
+    pub fn budget(&self) -> MipmapBudget {
+        let bytes = self.levels.iter().map(|level| level.data.len()).sum();
+        let available = match self.tlut {
+            Some(_) => TMEM::HIGH_HALF,
+            None => TMEM::SIZE,
+        };
+
+        MipmapBudget {
+            bytes,
+            available,
+            fits: bytes <= available,
+        }
+    }
+
+    // quantizes every level against the same palette so they can share a TLUT
+    fn quantize(
+        images: &[&PNGImage],
+        format: ImageFormat,
+        tlut_type: TLUTType,
+        entries: usize,
+        options: &EncodeOptions,
+    ) -> Result<(Vec<Vec<u8>>, TLUT)> {
+        let rgba = images
+            .iter()
+            .map(|image| image.as_snapped_rgba8(options))
+            .collect::<Result<Vec<_>>>()?;
+        let palette = MedianCut::palette(&rgba.concat(), entries);
+        let tlut = TLUT::from_rgba16(&palette, format, tlut_type, options)?;
+
+        let natives = images
+            .iter()
+            .zip(&rgba)
+            .map(|(image, rgba)| {
+                let indices = MedianCut::map(rgba, image.width, &palette, options.dither);
+
+                match format {
+                    ImageFormat::CI4 => pack_nibbles(&indices, image.width, image.height),
+                    _ => Ok(indices),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((natives, tlut))
+    }
+
+    // averages every 2x2 block of pixels, odd edges reuse their last row or column
+    fn downsample(image: &PNGImage) -> Result<PNGImage> {
+        let rgba = image.as_rgba8()?;
+        let width = (image.width / 2).max(1);
+        let height = (image.height / 2).max(1);
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..4 {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| {
+                            let sx = (x * 2 + dx).min(image.width - 1);
+                            let sy = (y * 2 + dy).min(image.height - 1);
+                            let index = (sy * image.width + sx) as usize * 4 + channel;
+                            rgba[index] as u32
+                        })
+                        .sum();
+
+                    data.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        Ok(PNGImage {
+            data,
+            width,
+            height,
+            color_type: ColorType::Rgba,
+            palette: None,
+            trns: None,
+        })
+    }
+}
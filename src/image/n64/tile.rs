@@ -0,0 +1,113 @@
+use super::tmem::TMEM;
+use super::{ImageFormat, TLUTType};
+use crate::error::Result;
+
+// mirrors the state set up by SetTile and SetTileSize, coordinates are
+// in the same 10.2 fixed point format the RDP uses
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TileDescriptor {
+    pub format: ImageFormat,
+    // row stride and TMEM address, both in 64-bit words
+    pub line: u16,
+    pub tmem: u16,
+    // palette bank used by CI4 textures
+    pub palette: u8,
+    // clamp and mirror bits (TileDescriptor::MIRROR, TileDescriptor::CLAMP) for each axis
+    pub cms: u8,
+    pub cmt: u8,
+    pub masks: u8,
+    pub maskt: u8,
+    pub shifts: u8,
+    pub shiftt: u8,
+    pub sl: u16,
+    pub tl: u16,
+    pub sh: u16,
+    pub th: u16,
+}
+
+impl TileDescriptor {
+    pub const WRAP: u8 = 0x0;
+    pub const MIRROR: u8 = 0x1;
+    pub const CLAMP: u8 = 0x2;
+
+    // renders `width` by `height` texels of the tile starting at texel (s, t), one pixel
+    // per texel like a texture rectangle with a step of 1. coordinates go through the
+    // same shift, clamp, mirror and mask stages as on the RDP, so areas past the edges
+    // of the tile show how it wraps. the result is RGBA8.
+    pub fn render(
+        &self,
+        tmem: &TMEM,
+        tlut_type: TLUTType,
+        s: i32,
+        t: i32,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let texels_s = TileDescriptor::extent(self.masks, self.sl, self.sh);
+        let texels_t = TileDescriptor::extent(self.maskt, self.tl, self.th);
+
+        let tile = tmem.read_image(self.format, texels_s, texels_t, self.line, self.tmem)?;
+        let tlut = match self.format.tlut_entries() {
+            Some(_) => Some(tmem.read_tlut(256, tlut_type)?),
+            None => None,
+        };
+        let texels = tile.decode(tlut.as_ref(), self.palette)?;
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height as i32 {
+            let tt =
+                TileDescriptor::wrap(t + y, self.shiftt, self.cmt, self.maskt, self.tl, self.th);
+
+            for x in 0..width as i32 {
+                let ss = TileDescriptor::wrap(
+                    s + x,
+                    self.shifts,
+                    self.cms,
+                    self.masks,
+                    self.sl,
+                    self.sh,
+                );
+
+                let index = (tt as usize * texels_s as usize + ss as usize) * 4;
+                data.extend_from_slice(&texels[index..index + 4]);
+            }
+        }
+
+        Ok(data)
+    }
+
+    // number of texels along an axis that sampling can ever reach
+    fn extent(mask: u8, low: u16, high: u16) -> u32 {
+        let clamped = (high.saturating_sub(low) >> 2) as u32 + 1;
+        match mask.min(10) {
+            0 => clamped,
+            mask => clamped.max(1 << mask),
+        }
+    }
+
+    // maps a texel coordinate onto the tile: shift, move to the tile's origin, clamp
+    // to its size (always done when there's no mask) and finally mirror and mask
+    fn wrap(coordinate: i32, shift: u8, mode: u8, mask: u8, low: u16, high: u16) -> u32 {
+        let mut coordinate = match shift {
+            0 => coordinate,
+            1..=10 => coordinate >> shift,
+            _ => coordinate << (16 - shift.min(16)),
+        };
+        coordinate -= (low >> 2) as i32;
+
+        let mask = mask.min(10);
+        if mode & TileDescriptor::CLAMP != 0 || mask == 0 {
+            coordinate = coordinate.clamp(0, (high.saturating_sub(low) >> 2) as i32);
+        }
+
+        if mask != 0 {
+            if mode & TileDescriptor::MIRROR != 0 && coordinate & (1 << mask) != 0 {
+                coordinate = !coordinate;
+            }
+
+            coordinate &= (1 << mask) - 1;
+        }
+
+        coordinate as u32
+    }
+}
@@ -0,0 +1,207 @@
+use super::{pack_nibbles, ImageFormat, ImageSize, NativeImage, TLUTType, TLUT};
+use crate::error::{Error, Result};
+use std::io::Read;
+
+// a snapshot of the RDP's 4 KiB texture memory, only built through `TMEM::read` so
+// every texel address masked into it stays in bounds
+pub struct TMEM {
+    data: Vec<u8>,
+}
+
+// how much TMEM a texture loaded with LoadBlock/LoadTile takes up
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TMEMBudget {
+    // row stride in 64-bit words, rows are always padded to whole words
+    pub line: u16,
+    // bytes used including the padding, RGBA32 and YUV16 count both of their halves
+    pub bytes: usize,
+    pub without_tlut: TMEMFit,
+    // the TLUT takes up the upper half, which CI textures always need
+    pub with_tlut: TMEMFit,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TMEMFit {
+    pub available: usize,
+    pub fits: bool,
+    // largest width that fits at the given height and largest height at the given width
+    pub max_width: u32,
+    pub max_height: u32,
+}
+
+impl TMEM {
+    pub const SIZE: usize = 0x1000;
+    // the upper half holds the TLUT when one is loaded, as well as the
+    // blue and alpha halves of RGBA32 texels
+    pub const HIGH_HALF: usize = 0x800;
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if data.len() < TMEM::SIZE {
+            return Err(Error::TruncatedData {
+                expected: TMEM::SIZE,
+                actual: data.len(),
+            });
+        }
+
+        if data.len() > TMEM::SIZE {
+            return Err(Error::TrailingData {
+                expected: TMEM::SIZE,
+                actual: data.len(),
+            });
+        }
+
+        Ok(Self { data })
+    }
+
+    // reconstructs a linear texture from a tile in TMEM, `line` is the tile's row stride and
+    // `address` its TMEM address, both in 64-bit words like the tile descriptor fields
+    pub fn read_image(
+        &self,
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+        line: u16,
+        address: u16,
+    ) -> Result<NativeImage> {
+        let mut data = Vec::with_capacity(format.bytes_for(width, height));
+        let mut nibbles = Vec::new();
+
+        for t in 0..height {
+            for s in 0..width {
+                let texel = TMEM::texel_address(format, line, address, s, t);
+
+                match format.size() {
+                    // U and V sit in the lower half and the Y of each texel in the upper,
+                    // reading them in pairs gives back the U Y0 V Y1 order
+                    ImageSize::S16B if format == ImageFormat::YUV16 => {
+                        data.extend_from_slice(&[
+                            self.data[texel],
+                            self.data[texel + TMEM::HIGH_HALF],
+                        ]);
+                    }
+                    ImageSize::S4B => {
+                        let byte = self.data[texel];
+                        nibbles.push(if s % 2 == 0 { byte >> 4 } else { byte & 0x0F });
+                    }
+                    ImageSize::S8B => data.push(self.data[texel]),
+                    ImageSize::S16B => data.extend_from_slice(&self.data[texel..texel + 2]),
+                    ImageSize::S32B => {
+                        let high = texel + TMEM::HIGH_HALF;
+                        data.extend_from_slice(&self.data[texel..texel + 2]);
+                        data.extend_from_slice(&self.data[high..high + 2]);
+                    }
+                }
+            }
+        }
+
+        if format.size() == ImageSize::S4B {
+            data = pack_nibbles(&nibbles, width, height)?;
+        }
+
+        Ok(NativeImage {
+            format,
+            width,
+            height,
+            data,
+        })
+    }
+
+    // TLUT entries live in the upper half of TMEM, each one repeated across
+    // the four 16-bit lanes of its own 64-bit word
+    pub fn read_tlut(&self, entries: usize, tlut_type: TLUTType) -> Result<TLUT> {
+        if entries > TLUT::MAX_ENTRIES {
+            return Err(Error::TooManyColors {
+                colors: entries,
+                max: TLUT::MAX_ENTRIES,
+            });
+        }
+
+        let data = (0..entries)
+            .flat_map(|entry| {
+                let address = TMEM::HIGH_HALF + entry * 8;
+                [self.data[address], self.data[address + 1]]
+            })
+            .collect();
+
+        Ok(TLUT { data, tlut_type })
+    }
+
+    // byte address of the first byte of texel (s, t). odd rows have the 32-bit halves of
+    // every 64-bit word swapped, and formats that share TMEM with a TLUT or with their own
+    // upper half (RGBA32, YUV16) wrap around within the lower 2 KiB
+    fn texel_address(format: ImageFormat, line: u16, address: u16, s: u32, t: u32) -> usize {
+        let row = (address as usize + t as usize * line as usize) * 8;
+        let offset = match format.size() {
+            ImageSize::S16B if format == ImageFormat::YUV16 => s as usize,
+            ImageSize::S4B => s as usize / 2,
+            ImageSize::S8B => s as usize,
+            ImageSize::S16B | ImageSize::S32B => s as usize * 2,
+        };
+
+        let swap = if t % 2 == 1 { 4 } else { 0 };
+        let mask = match format {
+            ImageFormat::CI4 | ImageFormat::CI8 | ImageFormat::RGBA32 | ImageFormat::YUV16 => {
+                TMEM::HIGH_HALF - 1
+            }
+            _ => TMEM::SIZE - 1,
+        };
+
+        ((row + offset) ^ swap) & mask
+    }
+
+    // TMEM usage of a texture, with and without a TLUT taking up the upper half
+    pub fn budget(format: ImageFormat, width: u32, height: u32) -> Result<TMEMBudget> {
+        if width == 0 || height == 0 {
+            return Err(Error::DimensionMismatch { width, height });
+        }
+
+        let line = TMEM::line(format, width);
+        let bytes = TMEM::row_bytes(format, line) * height as usize;
+
+        let fit = |available: usize| {
+            let max_line = available / (TMEM::row_bytes(format, 1) * height as usize);
+            TMEMFit {
+                available,
+                fits: bytes <= available,
+                max_width: (max_line * TMEM::texels_per_word(format)) as u32,
+                max_height: (available / TMEM::row_bytes(format, line)) as u32,
+            }
+        };
+
+        let with_tlut = match format {
+            ImageFormat::RGBA32 | ImageFormat::YUV16 => 0,
+            _ => TMEM::HIGH_HALF,
+        };
+
+        Ok(TMEMBudget {
+            line,
+            bytes,
+            without_tlut: fit(TMEM::SIZE),
+            with_tlut: fit(with_tlut),
+        })
+    }
+
+    // RGBA32 and YUV16 rows only hold half of each texel (red and green, or chroma),
+    // the other half sits at the same spot in the upper half of TMEM
+    fn texels_per_word(format: ImageFormat) -> usize {
+        match format {
+            ImageFormat::RGBA32 => 4,
+            ImageFormat::YUV16 => 8,
+            _ => 64 / format.size().bits_per_pixel(),
+        }
+    }
+
+    pub(super) fn line(format: ImageFormat, width: u32) -> u16 {
+        (width as usize).div_ceil(TMEM::texels_per_word(format)) as u16
+    }
+
+    pub(super) fn row_bytes(format: ImageFormat, line: u16) -> usize {
+        match format {
+            ImageFormat::RGBA32 | ImageFormat::YUV16 => line as usize * 16,
+            _ => line as usize * 8,
+        }
+    }
+}
@@ -1,2 +1,9 @@
 mod color;
+pub mod compression;
+mod dither;
+mod error;
 pub mod image;
+mod quantize;
+pub mod rom;
+
+pub use error::{Error, Result};
@@ -1,39 +1,377 @@
-use clap::{arg, command, Parser};
-use farbe::image::n64::{ImageFormat, NativeImage, PNGImage};
-use std::io::{Read, Seek};
+use clap::{Parser, Subcommand};
+use farbe::compression;
+use farbe::image::n64::mipmap::Mipmap;
+use farbe::image::n64::tmem::{TMEMFit, TMEM};
+use farbe::image::n64::{
+    Dither, EncodeOptions, ImageFormat, IntensityMode, NativeImage, PNGImage, TLUTType, TLUT,
+};
+use farbe::rom::byte_order::ByteOrder;
+use farbe::rom::crc::Cic;
+use farbe::rom::{Rom, TextureSlot};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    input: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Report how much TMEM a texture takes up and whether it fits
+    Budget {
+        #[arg(short, long)]
+        format: ImageFormat,
+
+        #[arg(long)]
+        width: u32,
+
+        #[arg(long)]
+        height: u32,
+    },
+    /// Extract a texture from a .z64, .n64 or .v64 rom into a png
+    Extract(ExtractArgs),
+    /// Encode a png over a texture in a rom and recompute the header CRCs
+    Inject(InjectArgs),
+    /// Convert a rom between the big-endian (z64), byte swapped (v64) and little-endian (n64) orders
+    Swap {
+        rom: String,
+
+        #[arg(long)]
+        to: ByteOrder,
+
+        /// Defaults to the rom path with the extension of the new byte order
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ExtractArgs {
+    rom: String,
+
+    /// Where the texture starts in the rom, in decimal or 0x prefixed hex
+    #[arg(long, value_parser = parse_offset)]
+    offset: usize,
 
+    #[arg(short, long)]
+    format: ImageFormat,
+
+    #[arg(long)]
+    width: u32,
+
+    #[arg(long)]
+    height: u32,
+
+    /// Where the TLUT used as the palette of CI4/CI8 textures starts in the rom
+    #[arg(long, value_parser = parse_offset)]
+    tlut_offset: Option<usize>,
+
+    /// Whether the TLUT entries are RGBA16 or IA16 colors
+    #[arg(long, default_value = "rgba16")]
+    tlut_type: TLUTType,
+
+    /// Defaults to the rom path with the offset and .png appended
     #[arg(short, long)]
     output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    #[arg(required = true)]
+    input: Option<String>,
 
     #[arg(short, long)]
-    format: ImageFormat,
+    output: Option<String>,
+
+    #[arg(short, long, required = true)]
+    format: Option<ImageFormat>,
 
     #[arg(long)]
     width: Option<u32>,
 
     #[arg(long)]
     height: Option<u32>,
+
+    /// TLUT used as the palette of CI4/CI8 images: read when exporting to png,
+    /// written when converting to native (defaults to the output path with .tlut appended)
+    #[arg(long)]
+    tlut: Option<String>,
+
+    /// Whether the TLUT entries are RGBA16 or IA16 colors
+    #[arg(long, default_value = "rgba16")]
+    tlut_type: TLUTType,
+
+    /// Palette bank (0-15) of the TLUT used by CI4 images
+    #[arg(long, default_value_t = 0)]
+    palette: u8,
+
+    /// Native data has the odd row word swap textures get in TMEM
+    #[arg(long)]
+    interleaved: bool,
+
+    /// Number of mipmap levels (1-8) to generate when converting to native,
+    /// packed one after another with every row padded to 64-bit words
+    #[arg(long)]
+    mipmaps: Option<usize>,
+
+    #[command(flatten)]
+    encode: EncodeArgs,
+}
+
+// flags that control how pngs are encoded to native, shared by convert and inject
+#[derive(clap::Args, Debug)]
+struct EncodeArgs {
+    /// How to dither colors that lose precision when converting to native
+    #[arg(long, default_value = "none")]
+    dither: Dither,
+
+    /// How colors are reduced to intensities for the I and IA formats
+    #[arg(long, default_value = "rec709")]
+    intensity: IntensityMode,
+
+    /// Alpha at or above which pixels stay opaque in formats with 1-bit alpha
+    #[arg(long, default_value_t = 128)]
+    alpha_threshold: u8,
+
+    /// Multiply colors by their alpha before converting to native
+    #[arg(long)]
+    premultiply: bool,
+
+    /// Fill the color of transparent pixels from their neighbours to avoid dark fringes when filtering
+    #[arg(long)]
+    bleed: bool,
+}
+
+impl EncodeArgs {
+    fn options(&self) -> EncodeOptions {
+        EncodeOptions {
+            dither: self.dither,
+            intensity: self.intensity,
+            alpha_threshold: self.alpha_threshold,
+            premultiply: self.premultiply,
+            bleed: self.bleed,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct InjectArgs {
+    rom: String,
+
+    png: String,
+
+    /// Where the texture being replaced starts in the rom, in decimal or 0x prefixed hex
+    #[arg(long, value_parser = parse_offset)]
+    offset: usize,
+
+    #[arg(short, long)]
+    format: ImageFormat,
+
+    /// Width and height of the texture being replaced, the png has to be the same size
+    #[arg(long)]
+    width: u32,
+
+    #[arg(long)]
+    height: u32,
+
+    /// Where to write the TLUT of CI4/CI8 textures in the rom, required for those formats
+    #[arg(long, value_parser = parse_offset)]
+    tlut_offset: Option<usize>,
+
+    /// Whether the TLUT entries are RGBA16 or IA16 colors
+    #[arg(long, default_value = "rgba16")]
+    tlut_type: TLUTType,
+
+    /// CIC used to recompute the CRCs, detected from the boot code when not set
+    #[arg(long)]
+    cic: Option<Cic>,
+
+    #[command(flatten)]
+    encode: EncodeArgs,
+
+    /// Defaults to overwriting the rom, in the byte order it was read in
+    #[arg(short, long)]
+    output: Option<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    // create file out of input path
-    let input = std::fs::File::open(&args.input).unwrap();
-    let mut reader = std::io::BufReader::new(input);
+    match args.command {
+        Some(Command::Budget {
+            format,
+            width,
+            height,
+        }) => budget(format, width, height),
+        Some(Command::Extract(args)) => extract(args),
+        Some(Command::Inject(args)) => inject(args),
+        Some(Command::Swap { rom, to, output }) => swap(rom, to, output),
+        None => convert(args.convert),
+    }
+}
+
+fn budget(format: ImageFormat, width: u32, height: u32) {
+    let budget = match TMEM::budget(format, width, height) {
+        Ok(budget) => budget,
+        Err(error) => {
+            println!("Error: {}", error);
+            return;
+        }
+    };
+
+    println!(
+        "{:?} {}x{}: {} bytes, line of {} words",
+        format, width, height, budget.bytes, budget.line
+    );
 
-    // detect if input is png by reading the first 8 bytes
-    let mut magic = [0u8; 8];
-    reader.read_exact(&mut magic).unwrap();
-    let is_png = magic == [137, 80, 78, 71, 13, 10, 26, 10];
+    let report = |label: &str, fit: TMEMFit| {
+        println!(
+            "{}: {} in {} bytes, max width {} at height {}, max height {} at width {}",
+            label,
+            if fit.fits { "fits" } else { "doesn't fit" },
+            fit.available,
+            fit.max_width,
+            height,
+            fit.max_height,
+            width
+        );
+    };
 
-    // reset reader to start
-    reader.seek(std::io::SeekFrom::Start(0)).unwrap();
+    report("without tlut", budget.without_tlut);
+    report("with tlut", budget.with_tlut);
+}
+
+// reports an error from one of the rom subcommands and exits without writing anything
+fn fail(error: impl std::fmt::Display) -> ! {
+    println!("Error: {}", error);
+    std::process::exit(1);
+}
+
+fn read_rom(path: &str) -> Rom {
+    let input = std::fs::File::open(path).unwrap_or_else(|error| fail(error));
+    Rom::read(std::io::BufReader::new(input)).unwrap_or_else(|error| fail(error))
+}
+
+fn extract(args: ExtractArgs) {
+    let rom = read_rom(&args.rom);
+
+    let is_indexed = matches!(args.format, ImageFormat::CI4 | ImageFormat::CI8);
+    if is_indexed && args.tlut_offset.is_none() {
+        fail("extracting CI4/CI8 textures requires --tlut-offset to be set");
+    }
+
+    let image = rom
+        .image(args.offset, args.format, args.width, args.height)
+        .unwrap_or_else(|error| fail(error));
+    let tlut = args
+        .tlut_offset
+        .map(|offset| rom.tlut(offset, args.format, args.tlut_type))
+        .transpose()
+        .unwrap_or_else(|error| fail(error));
+
+    let output = args
+        .output
+        .unwrap_or_else(|| format!("{}.{:X}.png", args.rom, args.offset));
+    let output = std::fs::File::create(output).unwrap_or_else(|error| fail(error));
+    image
+        .as_png(&mut std::io::BufWriter::new(output), tlut.as_ref(), 0)
+        .unwrap_or_else(|error| fail(error));
+}
+
+fn inject(args: InjectArgs) {
+    let is_indexed = matches!(args.format, ImageFormat::CI4 | ImageFormat::CI8);
+    if is_indexed && args.tlut_offset.is_none() {
+        fail("injecting CI4/CI8 textures requires --tlut-offset to be set");
+    }
+
+    let mut rom = read_rom(&args.rom);
+
+    let input = std::fs::File::open(&args.png).unwrap_or_else(|error| fail(error));
+    let image = PNGImage::read(std::io::BufReader::new(input)).unwrap_or_else(|error| fail(error));
+    let options = args.encode.options();
+
+    let slot = TextureSlot {
+        offset: args.offset,
+        format: args.format,
+        width: args.width,
+        height: args.height,
+        tlut: args.tlut_offset.map(|offset| (offset, args.tlut_type)),
+    };
+
+    let cic = rom
+        .inject(&slot, &image, &options)
+        .and_then(|_| rom.update_crc(args.cic))
+        .unwrap_or_else(|error| fail(error));
+    println!("updated CRCs for {:?}", cic);
+
+    let output = args.output.unwrap_or(args.rom);
+    let output = std::fs::File::create(output).unwrap_or_else(|error| fail(error));
+    rom.write(std::io::BufWriter::new(output), rom.byte_order)
+        .unwrap_or_else(|error| fail(error));
+}
+
+fn swap(path: String, byte_order: ByteOrder, output: Option<String>) {
+    let rom = read_rom(&path);
+
+    println!("{:?} -> {:?}", rom.byte_order, byte_order);
+
+    let output = output.unwrap_or_else(|| {
+        let extension = format!("{:?}", byte_order).to_lowercase();
+        std::path::Path::new(&path)
+            .with_extension(extension)
+            .to_string_lossy()
+            .into_owned()
+    });
+    let output = std::fs::File::create(output).unwrap_or_else(|error| fail(error));
+    rom.write(std::io::BufWriter::new(output), byte_order)
+        .unwrap_or_else(|error| fail(error));
+}
+
+// offsets are usually given in hex, like the ones in rom maps and disassemblies
+fn parse_offset(offset: &str) -> Result<usize, String> {
+    let parsed = match offset
+        .strip_prefix("0x")
+        .or_else(|| offset.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => offset.parse(),
+    };
+
+    parsed.map_err(|error| error.to_string())
+}
+
+fn convert(args: ConvertArgs) {
+    let input_path = args.input.unwrap();
+    let format = args.format.unwrap();
+
+    // read the whole input, unwrapping it first when it's compressed. native input that's
+    // already the size of the texture is raw even if it starts like a compressed block
+    let input = std::fs::read(&input_path).unwrap();
+    let is_raw = match (args.width, args.height) {
+        (Some(width), Some(height)) => input.len() == format.bytes_for(width, height),
+        _ => false,
+    };
+    let input = match is_raw {
+        true => Ok(input),
+        false => compression::decompress(input),
+    };
+    let input = match input {
+        Ok(input) => input,
+        Err(error) => {
+            println!("Error: {}", error);
+            return;
+        }
+    };
+    let mut reader = input.as_slice();
+
+    // detect if input is png by its 8 byte signature
+    let is_png = input.starts_with(&[137, 80, 78, 71, 13, 10, 26, 10]);
 
     // do validations
     if !is_png && (args.width.is_none() || args.height.is_none()) {
@@ -41,32 +379,111 @@ fn main() {
         return;
     }
 
+    if args.mipmaps.is_some() && (!is_png || args.interleaved) {
+        println!(
+            "Error: --mipmaps only works when converting a png to native without --interleaved"
+        );
+        return;
+    }
+
+    let is_indexed = matches!(format, ImageFormat::CI4 | ImageFormat::CI8);
+    if !is_png && is_indexed && args.tlut.is_none() {
+        println!("Error: exporting CI4/CI8 to png requires --tlut to be set");
+        return;
+    }
+
     // set output in case one was not given, default: append .png or .format to the input name
     let output = args.output.unwrap_or_else(|| {
-        let mut output = args.input.clone();
+        let mut output = input_path.clone();
         if is_png {
             output.push_str(".png");
         } else {
-            output.push_str(&format!(".{:?}", args.format));
+            output.push_str(&format!(".{:?}", format));
         }
         output
     });
 
     if is_png {
         let image = PNGImage::read(&mut reader).unwrap();
-        let output = std::fs::File::create(output).unwrap();
-        let mut writer = std::io::BufWriter::new(output);
-        image.as_native(&mut writer, args.format).unwrap();
+        let options = args.encode.options();
+
+        if let Some(levels) = args.mipmaps {
+            let mipmap =
+                Mipmap::from_png(&image, format, args.tlut_type, levels, &options).unwrap();
+
+            if let Some(tlut) = mipmap.tlut.as_ref() {
+                let path = args.tlut.unwrap_or_else(|| format!("{}.tlut", output));
+                std::fs::write(path, &tlut.data).unwrap();
+            }
+
+            for (index, level) in mipmap.levels.iter().enumerate() {
+                println!(
+                    "level {}: {}x{}, line {}, tmem {}",
+                    index, level.width, level.height, level.line, level.tmem
+                );
+            }
+
+            let budget = mipmap.budget();
+            println!(
+                "{} in {} of {} bytes of TMEM",
+                if budget.fits { "fits" } else { "doesn't fit" },
+                budget.bytes,
+                budget.available
+            );
+
+            std::fs::write(output, mipmap.data()).unwrap();
+            return;
+        }
+
+        // color indexed formats take their indices and TLUT from the same quantization
+        let mut data = match is_indexed {
+            true => {
+                let (data, tlut) = image.as_indexed(format, args.tlut_type, &options).unwrap();
+                let path = args.tlut.unwrap_or_else(|| format!("{}.tlut", output));
+                std::fs::write(path, tlut.data).unwrap();
+                data
+            }
+            false => {
+                let mut data = Vec::new();
+                image.as_native(&mut data, format, &options).unwrap();
+                data
+            }
+        };
+
+        if args.interleaved {
+            let native = NativeImage {
+                format,
+                width: image.width(),
+                height: image.height(),
+                data,
+            };
+            data = native.interleave().unwrap().data;
+        }
+
+        std::fs::write(output, data).unwrap();
     } else {
-        let image = NativeImage::read(
+        let mut image = NativeImage::read(
             &mut reader,
-            args.format,
+            format,
             args.width.unwrap(),
             args.height.unwrap(),
         )
         .unwrap();
+
+        if args.interleaved {
+            image = image.deinterleave().unwrap();
+        }
+
+        let tlut = args.tlut.map(|path| {
+            let input = std::fs::File::open(path).unwrap();
+            let reader = std::io::BufReader::new(input);
+            TLUT::read(reader, args.tlut_type).unwrap()
+        });
+
         let output = std::fs::File::create(output).unwrap();
         let mut writer = std::io::BufWriter::new(output);
-        image.as_png(&mut writer).unwrap();
+        image
+            .as_png(&mut writer, tlut.as_ref(), args.palette)
+            .unwrap();
     }
 }
@@ -0,0 +1,224 @@
+use crate::color::R5G5B5A1;
+use crate::dither::{diffuse, Dither, MAGIC_SQUARE};
+use std::collections::BTreeMap;
+
+pub struct Quantized {
+    pub indices: Vec<u8>,
+    pub palette: Vec<u16>,
+}
+
+pub struct MedianCut {}
+
+impl MedianCut {
+    // reduces RGBA8 pixels to at most `max_colors` RGBA5551 colors, transparent pixels
+    // all share a single palette entry since RGBA5551 only has one bit of alpha
+    pub fn quantize(rgba: &[u8], width: u32, max_colors: usize, dither: Dither) -> Quantized {
+        let palette = MedianCut::palette(rgba, max_colors);
+        let indices = MedianCut::map(rgba, width, &palette, dither);
+
+        Quantized { indices, palette }
+    }
+
+    pub fn palette(rgba: &[u8], max_colors: usize) -> Vec<u16> {
+        let pixels: Vec<u16> = rgba
+            .chunks_exact(4)
+            .map(|chunk| MedianCut::encode(chunk[0], chunk[1], chunk[2], chunk[3]))
+            .collect();
+
+        let mut histogram: BTreeMap<u16, usize> = BTreeMap::new();
+        for pixel in pixels.iter().filter(|pixel| **pixel & 0x01 == 1) {
+            *histogram.entry(*pixel).or_insert(0) += 1;
+        }
+
+        let has_transparency = pixels.contains(&0);
+        let opaque_slots = max_colors - has_transparency as usize;
+
+        let mut palette = Vec::new();
+        if has_transparency {
+            palette.push(0);
+        }
+
+        let colors: Vec<(u16, usize)> = histogram.into_iter().collect();
+        if colors.len() <= opaque_slots {
+            palette.extend(colors.iter().map(|(color, _)| *color));
+        } else {
+            palette.extend(MedianCut::split(colors, opaque_slots));
+        }
+
+        palette
+    }
+
+    // picks the palette entry for every pixel. the ordered dither nudges opaque pixels by the
+    // magic square scaled to the rough spacing between palette colors before looking up the
+    // nearest entry, Floyd-Steinberg carries each pixel's error over to its neighbours
+    pub fn map(rgba: &[u8], width: u32, palette: &[u16], dither: Dither) -> Vec<u8> {
+        let mut lookup: BTreeMap<u16, u8> = BTreeMap::new();
+        let mut nearest = |pixel: u16| {
+            *lookup
+                .entry(pixel)
+                .or_insert_with(|| MedianCut::nearest(palette, pixel))
+        };
+
+        let opaque = palette.iter().filter(|color| **color & 0x01 == 1).count();
+        let spread = 255.0 / (opaque.max(1) as f32).cbrt();
+        let width = width as usize;
+
+        match dither {
+            Dither::None => rgba
+                .chunks_exact(4)
+                .map(|chunk| nearest(MedianCut::encode(chunk[0], chunk[1], chunk[2], chunk[3])))
+                .collect(),
+            Dither::Bayer => rgba
+                .chunks_exact(4)
+                .enumerate()
+                .map(|(index, chunk)| {
+                    let threshold = MAGIC_SQUARE[index / width % 4][index % width % 4];
+                    let offset = ((threshold as f32 + 0.5) / 8.0 - 0.5) * spread;
+                    let [r, g, b] = [chunk[0], chunk[1], chunk[2]]
+                        .map(|value| (value as f32 + offset).round().clamp(0.0, 255.0) as u8);
+
+                    nearest(MedianCut::encode(r, g, b, chunk[3]))
+                })
+                .collect(),
+            Dither::FloydSteinberg => {
+                let pixels = rgba.len() / 4;
+                let mut errors = [vec![0f32; pixels], vec![0f32; pixels], vec![0f32; pixels]];
+
+                rgba.chunks_exact(4)
+                    .enumerate()
+                    .map(|(index, chunk)| {
+                        let target: [f32; 3] = std::array::from_fn(|channel| {
+                            (chunk[channel] as f32 + errors[channel][index]).clamp(0.0, 255.0)
+                        });
+                        let [r, g, b] = target.map(|value| value.round() as u8);
+
+                        let entry = nearest(MedianCut::encode(r, g, b, chunk[3]));
+                        let color = palette[entry as usize];
+                        if color & 0x01 == 1 {
+                            let actual = R5G5B5A1::to_rgba(color);
+                            for channel in 0..3 {
+                                let error = target[channel] - actual[channel] as f32;
+                                diffuse(&mut errors[channel], index, width, error);
+                            }
+                        }
+
+                        entry
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // repeatedly splits the box with the widest channel range at its weighted median
+    // until there are as many boxes as colors, each box becomes its weighted average
+    fn split(colors: Vec<(u16, usize)>, max_colors: usize) -> Vec<u16> {
+        let mut boxes = vec![colors];
+
+        while boxes.len() < max_colors {
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, colors)| colors.len() > 1)
+                .map(|(index, colors)| {
+                    let (channel, range) = MedianCut::widest_channel(colors);
+                    (index, channel, range)
+                })
+                .max_by_key(|(index, _, range)| (*range, std::cmp::Reverse(*index)));
+
+            let Some((index, channel, _)) = widest else {
+                break;
+            };
+
+            let mut colors = boxes.swap_remove(index);
+            colors.sort_by_key(|(color, _)| (MedianCut::channels(*color)[channel], *color));
+
+            let total: usize = colors.iter().map(|(_, count)| count).sum();
+            let mut seen = 0;
+            let mut median = 1;
+            for (position, (_, count)) in colors.iter().enumerate() {
+                seen += count;
+                if seen * 2 >= total {
+                    median = (position + 1).clamp(1, colors.len() - 1);
+                    break;
+                }
+            }
+
+            let upper = colors.split_off(median);
+            boxes.push(colors);
+            boxes.push(upper);
+        }
+
+        boxes
+            .iter()
+            .map(|colors| MedianCut::average(colors))
+            .collect()
+    }
+
+    fn widest_channel(colors: &[(u16, usize)]) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let values = colors
+                    .iter()
+                    .map(|(color, _)| MedianCut::channels(*color)[channel]);
+                let min = values.clone().min().unwrap_or(0);
+                let max = values.max().unwrap_or(0);
+                (channel, max - min)
+            })
+            .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+            .unwrap()
+    }
+
+    fn average(colors: &[(u16, usize)]) -> u16 {
+        let total: usize = colors.iter().map(|(_, count)| count).sum();
+        let mut sums = [0usize; 3];
+
+        for (color, count) in colors {
+            for (sum, value) in sums.iter_mut().zip(MedianCut::channels(*color)) {
+                *sum += value as usize * count;
+            }
+        }
+
+        let [r, g, b] = sums.map(|sum| ((sum + total / 2) / total) as u16);
+        (r << 11) | (g << 6) | (b << 1) | 0x01
+    }
+
+    // transparent pixels all collapse into the same color. pixels come in with their alpha
+    // already snapped to 0 or 255, so only fully opaque ones are kept opaque
+    fn encode(r: u8, g: u8, b: u8, a: u8) -> u16 {
+        match R5G5B5A1::from_rgba(r, g, b, a, u8::MAX) {
+            pixel if pixel & 0x01 == 0 => 0,
+            pixel => pixel,
+        }
+    }
+
+    fn nearest(palette: &[u16], pixel: u16) -> u8 {
+        if pixel & 0x01 == 0 {
+            if let Some(index) = palette.iter().position(|color| color & 0x01 == 0) {
+                return index as u8;
+            }
+        }
+
+        let target = MedianCut::channels(pixel);
+        palette
+            .iter()
+            .enumerate()
+            .filter(|(_, color)| **color & 0x01 == pixel & 0x01)
+            .min_by_key(|(_, color)| {
+                MedianCut::channels(**color)
+                    .iter()
+                    .zip(target)
+                    .map(|(a, b)| (*a as i32 - b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|(index, _)| index as u8)
+            .unwrap_or(0)
+    }
+
+    fn channels(pixel: u16) -> [u8; 3] {
+        [
+            ((pixel >> 11) & 0x1F) as u8,
+            ((pixel >> 6) & 0x1F) as u8,
+            ((pixel >> 1) & 0x1F) as u8,
+        ]
+    }
+}
@@ -0,0 +1,148 @@
+pub mod byte_order;
+pub mod crc;
+
+use crate::error::{Error, Result};
+use crate::image::n64::{EncodeOptions, ImageFormat, NativeImage, PNGImage, TLUTType, TLUT};
+use byte_order::ByteOrder;
+use crc::Cic;
+use std::io::{Read, Write};
+
+// a cartridge image, always kept in the big-endian (.z64) byte order the console sees
+pub struct Rom {
+    pub data: Vec<u8>,
+    // the order the rom was read in, so it can be written back the same way
+    pub byte_order: ByteOrder,
+}
+
+// where a texture sits in the rom and how it's encoded, color indexed textures also
+// need the offset and type of their TLUT
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureSlot {
+    pub offset: usize,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub tlut: Option<(usize, TLUTType)>,
+}
+
+impl Rom {
+    // reads a .z64, .n64 or .v64 rom, putting its bytes back in big-endian order
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let byte_order = ByteOrder::detect(&data)?;
+        byte_order.convert(&mut data, ByteOrder::Z64);
+
+        Ok(Self { data, byte_order })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W, byte_order: ByteOrder) -> Result<()> {
+        let mut data = self.data.clone();
+        ByteOrder::Z64.convert(&mut data, byte_order);
+
+        writer.write_all(&data)?;
+        Ok(())
+    }
+
+    // reads a texture starting at `offset`, anything after it is left alone
+    pub fn image(
+        &self,
+        offset: usize,
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<NativeImage> {
+        NativeImage::read_prefix(
+            self.slice(offset, format.bytes_for(width, height))?,
+            format,
+            width,
+            height,
+        )
+    }
+
+    // reads a TLUT with as many entries as the color indexed format can address
+    pub fn tlut(&self, offset: usize, format: ImageFormat, tlut_type: TLUTType) -> Result<TLUT> {
+        let entries = format
+            .tlut_entries()
+            .ok_or(Error::NotColorIndexed(format))?;
+
+        TLUT::read(self.slice(offset, entries * 2)?, tlut_type)
+    }
+
+    // encodes a png over the texture in `slot`, the png has to be exactly as wide and tall as
+    // the texture it replaces. color indexed textures get their TLUT written too
+    pub fn inject(
+        &mut self,
+        slot: &TextureSlot,
+        image: &PNGImage,
+        options: &EncodeOptions,
+    ) -> Result<()> {
+        if image.width() != slot.width || image.height() != slot.height {
+            return Err(Error::DimensionMismatch {
+                width: slot.width,
+                height: slot.height,
+            });
+        }
+
+        let (data, tlut) = match slot.format.tlut_entries() {
+            Some(_) => {
+                let (offset, tlut_type) = slot.tlut.ok_or(Error::MissingTLUT(slot.format))?;
+                let (data, tlut) = image.as_indexed(slot.format, tlut_type, options)?;
+                (data, Some((offset, tlut)))
+            }
+            None => {
+                let mut data = Vec::new();
+                image.as_native(&mut data, slot.format, options)?;
+                (data, None)
+            }
+        };
+
+        // check both ranges first so a bad TLUT offset doesn't leave a half written rom
+        self.slice(slot.offset, data.len())?;
+        if let Some((offset, tlut)) = &tlut {
+            self.slice(*offset, tlut.data.len())?;
+            self.write_at(*offset, &tlut.data)?;
+        }
+
+        self.write_at(slot.offset, &data)
+    }
+
+    // overwrites the bytes at `offset`, which all have to be inside the rom already
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        let length = self.data.len();
+        self.data
+            .get_mut(offset..offset.saturating_add(data.len()))
+            .ok_or(Error::TruncatedData {
+                expected: offset.saturating_add(data.len()),
+                actual: length,
+            })?
+            .copy_from_slice(data);
+
+        Ok(())
+    }
+
+    // recomputes the header CRCs so the boot code accepts the rom, the CIC is detected
+    // from the boot code when it isn't given
+    pub fn update_crc(&mut self, cic: Option<Cic>) -> Result<Cic> {
+        let cic = match cic {
+            Some(cic) => cic,
+            None => Cic::detect(&self.data)?,
+        };
+
+        let [crc1, crc2] = crc::checksum(&self.data, cic)?;
+        self.data[crc::CRC_OFFSET..crc::CRC_OFFSET + 4].copy_from_slice(&crc1.to_be_bytes());
+        self.data[crc::CRC_OFFSET + 4..crc::CRC_OFFSET + 8].copy_from_slice(&crc2.to_be_bytes());
+
+        Ok(cic)
+    }
+
+    fn slice(&self, offset: usize, length: usize) -> Result<&[u8]> {
+        self.data
+            .get(offset..offset.saturating_add(length))
+            .ok_or(Error::TruncatedData {
+                expected: offset.saturating_add(length),
+                actual: self.data.len(),
+            })
+    }
+}
@@ -0,0 +1,52 @@
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+
+// the byte orders roms get dumped in, named after the extensions that usually go with them
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum ByteOrder {
+    // big-endian, the order the console reads the cartridge in
+    Z64,
+    // the bytes of every 16-bit half word swapped
+    V64,
+    // every 32-bit word stored little-endian
+    N64,
+}
+
+impl ByteOrder {
+    // the first word of every rom header is 0x80371240 in big-endian order
+    pub fn detect(data: &[u8]) -> Result<Self> {
+        let magic: [u8; 4] = match data.get(..4) {
+            Some(magic) => magic.try_into().unwrap(),
+            None => {
+                return Err(Error::TruncatedData {
+                    expected: 4,
+                    actual: data.len(),
+                })
+            }
+        };
+
+        match magic {
+            [0x80, 0x37, 0x12, 0x40] => Ok(ByteOrder::Z64),
+            [0x37, 0x80, 0x40, 0x12] => Ok(ByteOrder::V64),
+            [0x40, 0x12, 0x37, 0x80] => Ok(ByteOrder::N64),
+            _ => Err(Error::UnknownByteOrder(magic)),
+        }
+    }
+
+    // rewrites data in this order into `order` in place
+    pub fn convert(self, data: &mut [u8], order: ByteOrder) {
+        if self != order {
+            self.swap(data);
+            order.swap(data);
+        }
+    }
+
+    // both swaps undo themselves, so the same one goes to and from big-endian
+    fn swap(self, data: &mut [u8]) {
+        match self {
+            ByteOrder::Z64 => {}
+            ByteOrder::V64 => data.chunks_exact_mut(2).for_each(|half| half.swap(0, 1)),
+            ByteOrder::N64 => data.chunks_exact_mut(4).for_each(|word| word.reverse()),
+        }
+    }
+}
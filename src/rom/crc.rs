@@ -0,0 +1,94 @@
+use crate::error::{Error, Result};
+use clap::ValueEnum;
+
+// the lockout chips boot code is paired with, each one seeds the checksum differently
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Cic {
+    Cic6101,
+    Cic6102,
+    Cic6103,
+    Cic6105,
+    Cic6106,
+}
+
+impl Cic {
+    // the boot code sits between the header and the start of the checksummed area
+    const BOOT_CODE: std::ops::Range<usize> = 0x40..0x1000;
+
+    // identifies the chip from a crc32 of the boot code the rom ships with
+    pub fn detect(data: &[u8]) -> Result<Self> {
+        let boot_code = data.get(Cic::BOOT_CODE).ok_or(Error::TruncatedData {
+            expected: Cic::BOOT_CODE.end,
+            actual: data.len(),
+        })?;
+
+        match crc32fast::hash(boot_code) {
+            0x6170A4A1 => Ok(Cic::Cic6101),
+            0x90BB6CB5 => Ok(Cic::Cic6102),
+            0x0B050EE0 => Ok(Cic::Cic6103),
+            0x98BC2C86 => Ok(Cic::Cic6105),
+            0xACC8580A => Ok(Cic::Cic6106),
+            crc => Err(Error::UnknownCic(crc)),
+        }
+    }
+
+    fn seed(self) -> u32 {
+        match self {
+            Cic::Cic6101 | Cic::Cic6102 => 0xF8CA4DDC,
+            Cic::Cic6103 => 0xA3886759,
+            Cic::Cic6105 => 0xDF26F436,
+            Cic::Cic6106 => 0x1FEA617A,
+        }
+    }
+}
+
+// where the two header CRCs live and the first megabyte after the boot code they cover
+pub const CRC_OFFSET: usize = 0x10;
+pub const CHECKSUM_START: usize = 0x1000;
+pub const CHECKSUM_LENGTH: usize = 0x100000;
+
+// the two words the boot code compares against before jumping to the game
+pub fn checksum(data: &[u8], cic: Cic) -> Result<[u32; 2]> {
+    let end = CHECKSUM_START + CHECKSUM_LENGTH;
+    let covered = data.get(CHECKSUM_START..end).ok_or(Error::TruncatedData {
+        expected: end,
+        actual: data.len(),
+    })?;
+
+    let word = |bytes: &[u8]| u32::from_be_bytes(bytes.try_into().unwrap());
+    let seed = cic.seed();
+    let (mut t1, mut t2, mut t3, mut t4, mut t5, mut t6) = (seed, seed, seed, seed, seed, seed);
+
+    for (index, bytes) in covered.chunks_exact(4).enumerate() {
+        let d = word(bytes);
+
+        let (sum, overflowed) = t6.overflowing_add(d);
+        if overflowed {
+            t4 = t4.wrapping_add(1);
+        }
+        t6 = sum;
+        t3 ^= d;
+
+        let r = d.rotate_left(d & 0x1F);
+        t5 = t5.wrapping_add(r);
+        t2 ^= if t2 > d { r } else { t6 ^ d };
+
+        // 6105 mixes in words from its own boot code
+        t1 = t1.wrapping_add(match cic {
+            Cic::Cic6105 => {
+                let address = 0x750 + ((index * 4) & 0xFF);
+                word(&data[address..address + 4]) ^ d
+            }
+            _ => t5 ^ d,
+        });
+    }
+
+    Ok(match cic {
+        Cic::Cic6103 => [(t6 ^ t4).wrapping_add(t3), (t5 ^ t2).wrapping_add(t1)],
+        Cic::Cic6106 => [
+            t6.wrapping_mul(t4).wrapping_add(t3),
+            t5.wrapping_mul(t2).wrapping_add(t1),
+        ],
+        _ => [t6 ^ t4 ^ t3, t5 ^ t2 ^ t1],
+    })
+}
@@ -0,0 +1,60 @@
+// fixtures shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use farbe::image::n64::PNGImage;
+use png::{BitDepth, ColorType};
+
+// encodes a png of any color type and bit depth, with PLTE and tRNS chunks when given
+pub fn encode_png(
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    palette: Option<&[u8]>,
+    trns: Option<&[u8]>,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    if let Some(palette) = palette {
+        encoder.set_palette(palette.to_vec());
+    }
+    if let Some(trns) = trns {
+        encoder.set_trns(trns.to_vec());
+    }
+
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(data).unwrap();
+    writer.finish().unwrap();
+
+    output
+}
+
+// reads back an 8-bit RGBA png of the given pixels
+pub fn rgba_png(width: u32, height: u32, data: &[u8]) -> PNGImage {
+    let bytes = encode_png(
+        width,
+        height,
+        ColorType::Rgba,
+        BitDepth::Eight,
+        None,
+        None,
+        data,
+    );
+    PNGImage::read(bytes.as_slice()).unwrap()
+}
+
+// deterministic xorshift noise, for data that doesn't compress or quantize neatly
+pub fn noise(length: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 24) as u8
+        })
+        .collect()
+}
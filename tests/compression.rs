@@ -0,0 +1,228 @@
+mod common;
+
+use common::noise;
+use farbe::compression::{self, mio0, yay0, yaz0};
+use farbe::image::n64::{ImageFormat, NativeImage};
+use farbe::Error;
+
+// a mix of runs, repeated rows and noise like most textures have
+fn samples() -> Vec<Vec<u8>> {
+    let rows: Vec<u8> = (0..64u8)
+        .flat_map(|y| (0..64u8).map(move |x| (x / 8) ^ ((y / 8) * 16)))
+        .collect();
+
+    vec![
+        Vec::new(),
+        vec![0x42],
+        b"ABCABCABC".to_vec(),
+        vec![0; 10000],
+        rows,
+        noise(5000, 0x1234_5678),
+    ]
+}
+
+// MARK: - MIO0
+
+#[test]
+fn mio0_round_trips() {
+    for data in samples() {
+        let encoded = mio0::encode(&data);
+        assert_eq!(mio0::decode(&encoded).unwrap(), data);
+    }
+}
+
+#[test]
+fn mio0_encodes_back_references() {
+    let encoded = mio0::encode(b"ABCABCABC");
+    assert_eq!(
+        encoded,
+        [
+            b'M', b'I', b'O', b'0', 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00,
+            0x00, 0x16, 0xE0, 0x00, 0x00, 0x00, 0x30, 0x02, b'A', b'B', b'C',
+        ]
+    );
+}
+
+#[test]
+fn mio0_shrinks_repetitive_data() {
+    let encoded = mio0::encode(&[0x11; 0x1000]);
+    assert!(encoded.len() < 0x200, "{} bytes", encoded.len());
+}
+
+#[test]
+fn mio0_rejects_corrupt_data() {
+    assert!(matches!(
+        mio0::decode(b"Yay0\0\0\0\x01"),
+        Err(Error::CorruptCompressedData)
+    ));
+
+    // a back reference as the very first step has nothing to copy from
+    let data: &[u8] = &[
+        b'M', b'I', b'O', b'0', 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00,
+        0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    assert!(matches!(
+        mio0::decode(data),
+        Err(Error::CorruptCompressedData)
+    ));
+
+    // a header claiming 4 GiB fails on the missing bitfield instead of allocating it
+    let data: &[u8] = &[
+        b'M', b'I', b'O', b'0', 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00,
+        0x10,
+    ];
+    assert!(matches!(
+        mio0::decode(data),
+        Err(Error::TruncatedData {
+            expected: 20,
+            actual: 16
+        })
+    ));
+
+    let encoded = mio0::encode(b"ABCABCABC");
+    assert!(matches!(
+        mio0::decode(&encoded[..encoded.len() - 1]),
+        Err(Error::TruncatedData {
+            expected: 25,
+            actual: 24
+        })
+    ));
+}
+
+#[test]
+fn mio0_input_is_decompressed_when_read() {
+    let texture: Vec<u8> = (0..32u8).collect();
+    let encoded = mio0::encode(&texture);
+    assert_eq!(compression::decompress(encoded.clone()).unwrap(), texture);
+
+    let image = NativeImage::read(encoded.as_slice(), ImageFormat::RGBA16, 4, 4).unwrap();
+    assert_eq!(image.data, texture);
+}
+
+#[test]
+fn raw_input_that_looks_compressed_is_read_as_is() {
+    let mut texture = b"Yaz0".to_vec();
+    texture.extend(0..12u8);
+
+    let image = NativeImage::read(texture.as_slice(), ImageFormat::I8, 4, 4).unwrap();
+    assert_eq!(image.data, texture);
+}
+
+// MARK: - Yay0
+
+#[test]
+fn yay0_round_trips() {
+    for data in samples() {
+        let encoded = yay0::encode(&data);
+        assert_eq!(yay0::decode(&encoded).unwrap(), data);
+    }
+}
+
+#[test]
+fn yay0_encodes_short_and_long_back_references() {
+    let encoded = yay0::encode(b"ABCABCABC");
+    assert_eq!(
+        encoded,
+        [
+            b'Y', b'a', b'y', b'0', 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00,
+            0x00, 0x16, 0xE0, 0x00, 0x00, 0x00, 0x40, 0x02, b'A', b'B', b'C',
+        ]
+    );
+
+    // runs of 18 or more bytes take their length from the literal stream
+    let encoded = yay0::encode(&[0; 100]);
+    assert_eq!(
+        encoded,
+        [
+            b'Y', b'a', b'y', b'0', 0x00, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00,
+            0x00, 0x16, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x51,
+        ]
+    );
+}
+
+#[test]
+fn yay0_rejects_corrupt_data() {
+    assert!(matches!(
+        yay0::decode(b"MIO0\0\0\0\x01"),
+        Err(Error::CorruptCompressedData)
+    ));
+
+    // a header claiming 4 GiB fails on the missing bitfield instead of allocating it
+    assert!(matches!(
+        yay0::decode(b"Yay0\xFF\xFF\xFF\xFF\0\0\0\x10\0\0\0\x10"),
+        Err(Error::TruncatedData {
+            expected: 20,
+            actual: 16
+        })
+    ));
+}
+
+// MARK: - Yaz0
+
+#[test]
+fn yaz0_round_trips() {
+    for data in samples() {
+        let encoded = yaz0::encode(&data);
+        assert_eq!(yaz0::decode(&encoded).unwrap(), data);
+    }
+}
+
+#[test]
+fn yaz0_encodes_short_and_long_back_references() {
+    let header = |length: u8| {
+        let mut header = b"Yaz0".to_vec();
+        header.extend_from_slice(&[0, 0, 0, length, 0, 0, 0, 0, 0, 0, 0, 0]);
+        header
+    };
+
+    let mut expected = header(9);
+    expected.extend_from_slice(&[0xE0, b'A', b'B', b'C', 0x40, 0x02]);
+    assert_eq!(yaz0::encode(b"ABCABCABC"), expected);
+
+    let mut expected = header(100);
+    expected.extend_from_slice(&[0x80, 0x00, 0x00, 0x00, 0x51]);
+    assert_eq!(yaz0::encode(&[0; 100]), expected);
+}
+
+#[test]
+fn yaz0_rejects_corrupt_data() {
+    let mut data = b"Yaz0".to_vec();
+    data.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x10, 0x00]);
+    assert!(matches!(
+        yaz0::decode(&data),
+        Err(Error::CorruptCompressedData)
+    ));
+    assert!(matches!(
+        yaz0::decode(&data[..12]),
+        Err(Error::TruncatedData {
+            expected: 17,
+            actual: 12
+        })
+    ));
+
+    // a header claiming 4 GiB fails on the missing flags instead of allocating them
+    let mut data = b"Yaz0".to_vec();
+    data.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(matches!(
+        yaz0::decode(&data),
+        Err(Error::TruncatedData {
+            expected: 17,
+            actual: 16
+        })
+    ));
+}
+
+#[test]
+fn every_format_is_detected() {
+    let texture: Vec<u8> = (0..64u8).map(|i| i / 4).collect();
+
+    for encoded in [
+        mio0::encode(&texture),
+        yay0::encode(&texture),
+        yaz0::encode(&texture),
+    ] {
+        assert_eq!(compression::decompress(encoded).unwrap(), texture);
+    }
+
+    assert_eq!(compression::decompress(texture.clone()).unwrap(), texture);
+}
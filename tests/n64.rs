@@ -1,4 +1,28 @@
-use farbe::image::n64::{ImageFormat, NativeImage, PNGImage};
+mod common;
+
+use clap::ValueEnum;
+use common::{encode_png, noise, rgba_png};
+use farbe::image::n64::mipmap::Mipmap;
+use farbe::image::n64::tile::TileDescriptor;
+use farbe::image::n64::tmem::TMEM;
+use farbe::image::n64::{
+    Dither, EncodeOptions, ImageFormat, IntensityMode, NativeImage, PNGImage, TLUTType,
+    YUVCoefficients, TLUT,
+};
+use farbe::Error;
+use png::{BitDepth, ColorType};
+
+// builds a TLUT whose entries ramp from black to white, alternating opacity
+fn grayscale_tlut(entries: u16) -> TLUT {
+    let mut bytes = Vec::new();
+    for i in 0..entries {
+        let value = i * 31 / (entries - 1);
+        let pixel = value << 11 | value << 6 | value << 1 | (i % 2);
+        bytes.extend_from_slice(&pixel.to_be_bytes());
+    }
+
+    TLUT::read(bytes.as_slice(), TLUTType::RGBA16).unwrap()
+}
 
 #[test]
 fn rgba32_to_png() {
@@ -7,7 +31,7 @@ fn rgba32_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.rgba32.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -17,7 +41,7 @@ fn rgba16_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.rgba16.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -27,7 +51,7 @@ fn i4_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.i4.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -37,7 +61,7 @@ fn i8_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.i8.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -47,7 +71,7 @@ fn ia4_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.ia4.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -57,7 +81,7 @@ fn ia8_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.ia8.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
@@ -67,27 +91,289 @@ fn ia16_to_png() {
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.ia16.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, None, 0).unwrap();
 }
 
 #[test]
 fn ci4_to_png() {
     let bytes: &[u8] = include_bytes!("n64/test.ci4");
     let image = NativeImage::read(bytes, ImageFormat::CI4, 48, 85).unwrap();
+    let tlut = grayscale_tlut(16);
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.ci4.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, Some(&tlut), 0).unwrap();
 }
 
 #[test]
 fn ci8_to_png() {
     let bytes: &[u8] = include_bytes!("n64/test.ci8");
     let image = NativeImage::read(bytes, ImageFormat::CI8, 32, 32).unwrap();
+    let tlut = grayscale_tlut(256);
 
     // write to file
     let mut output_file = std::fs::File::create("tests_output/n64/test.ci8.png").unwrap();
-    image.as_png(&mut output_file).unwrap();
+    image.as_png(&mut output_file, Some(&tlut), 0).unwrap();
+}
+
+#[test]
+fn ci4_to_png_writes_palette() {
+    let bytes: &[u8] = &[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF];
+    let image = NativeImage::read(bytes, ImageFormat::CI4, 4, 4).unwrap();
+    let tlut = grayscale_tlut(16);
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_png(&mut output, Some(&tlut), 0).unwrap();
+
+    let decoder = png::Decoder::new(output.as_slice());
+    let reader = decoder.read_info().unwrap();
+    let info = reader.info();
+    let palette = info.palette.as_ref().unwrap();
+    let trns = info.trns.as_ref().unwrap();
+
+    assert_eq!(info.color_type, ColorType::Indexed);
+    assert_eq!(palette.len(), 16 * 3);
+    assert_eq!(trns.len(), 16);
+    assert_eq!(&palette[..3], &[0, 0, 0]);
+    assert_eq!(&palette[45..], &[255, 255, 255]);
+    assert_eq!(&trns[..2], &[0, 255]);
+}
+
+#[test]
+fn ci8_to_png_requires_tlut() {
+    let bytes: &[u8] = &[0; 16];
+    let image = NativeImage::read(bytes, ImageFormat::CI8, 4, 4).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    let result = image.as_png(&mut output, None, 0);
+
+    assert!(matches!(result, Err(Error::MissingTLUT(ImageFormat::CI8))));
+}
+
+#[test]
+fn truncated_native_image_returns_error() {
+    let bytes: &[u8] = &[0; 7];
+    let result = NativeImage::read(bytes, ImageFormat::IA4, 4, 4);
+
+    assert!(matches!(
+        result,
+        Err(Error::TruncatedData {
+            expected: 8,
+            actual: 7
+        })
+    ));
+}
+
+#[test]
+fn image_format_bytes_for() {
+    assert_eq!(ImageFormat::I4.bytes_for(16, 16), 128);
+    assert_eq!(ImageFormat::CI4.bytes_for(5, 3), 9);
+    assert_eq!(ImageFormat::IA8.bytes_for(5, 3), 15);
+    assert_eq!(ImageFormat::RGBA16.bytes_for(32, 64), 4096);
+    assert_eq!(ImageFormat::RGBA32.bytes_for(4, 2), 32);
+}
+
+#[test]
+fn native_image_read_rejects_trailing_data() {
+    let bytes: &[u8] = &[0; 10];
+    let result = NativeImage::read(bytes, ImageFormat::I8, 3, 3);
+
+    assert!(matches!(
+        result,
+        Err(Error::TrailingData {
+            expected: 9,
+            actual: 10
+        })
+    ));
+}
+
+#[test]
+fn native_image_read_prefix_allows_trailing_data() {
+    let bytes: &[u8] = &[1, 2, 3, 4, 5, 6];
+    let mut reader = bytes;
+
+    let image = NativeImage::read_prefix(&mut reader, ImageFormat::IA16, 2, 1).unwrap();
+
+    assert_eq!(image.data, vec![1, 2, 3, 4]);
+    assert_eq!(reader, &[5, 6]);
+    assert!(NativeImage::read_prefix(&bytes[..3], ImageFormat::IA16, 2, 1).is_err());
+}
+
+#[test]
+fn i4_odd_width_to_png() {
+    // 3x2 with each row padded to a whole byte
+    let bytes: &[u8] = &[0x12, 0x30, 0x45, 0x60];
+    let image = NativeImage::read(bytes, ImageFormat::I4, 3, 2).unwrap();
+
+    let decoded = image.decode(None, 0).unwrap();
+    let intensities: Vec<u8> = decoded.chunks_exact(4).map(|pixel| pixel[0]).collect();
+
+    assert_eq!(intensities, vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+}
+
+#[test]
+fn ia4_odd_width_to_png() {
+    let bytes: &[u8] = &[0x3F, 0xF0, 0x10, 0x00];
+    let image = NativeImage::read(bytes, ImageFormat::IA4, 3, 2).unwrap();
+
+    let decoded = image.decode(None, 0).unwrap();
+    let alphas: Vec<u8> = decoded.chunks_exact(4).map(|pixel| pixel[3]).collect();
+
+    assert_eq!(alphas, vec![255, 255, 255, 255, 0, 0]);
+}
+
+#[test]
+fn ci4_odd_width_round_trip() {
+    let bytes: &[u8] = &[0x12, 0x30, 0x45, 0x60];
+    let image = NativeImage::read(bytes, ImageFormat::CI4, 3, 2).unwrap();
+    let tlut = grayscale_tlut(16);
+
+    let mut png: Vec<u8> = Vec::new();
+    image.as_png(&mut png, Some(&tlut), 0).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    PNGImage::read(png.as_slice())
+        .unwrap()
+        .as_ci4(&mut output, &EncodeOptions::default())
+        .unwrap();
+
+    assert_eq!(output, bytes);
+}
+
+#[test]
+fn odd_width_png_to_i4_and_ia4() {
+    let png = encode_png(
+        3,
+        2,
+        ColorType::GrayscaleAlpha,
+        BitDepth::Eight,
+        None,
+        None,
+        &[
+            0x10, 255, 0x20, 255, 0x30, 255, //
+            0x40, 0, 0x50, 0, 0xE0, 0,
+        ],
+    );
+    let image = PNGImage::read(png.as_slice()).unwrap();
+
+    let mut i4: Vec<u8> = Vec::new();
+    image.as_i4(&mut i4, &EncodeOptions::default()).unwrap();
+    let mut ia4: Vec<u8> = Vec::new();
+    image.as_ia4(&mut ia4, &EncodeOptions::default()).unwrap();
+
+    assert_eq!(i4, vec![0x12, 0x30, 0x45, 0xE0]);
+    assert_eq!(ia4, vec![0x13, 0x30, 0x44, 0xE0]);
+}
+
+#[test]
+fn rgba16_interleave_swaps_odd_rows() {
+    let bytes: Vec<u8> = (0..16).collect();
+    let image = NativeImage::read(bytes.as_slice(), ImageFormat::RGBA16, 4, 2).unwrap();
+
+    let interleaved = image.interleave().unwrap();
+
+    assert_eq!(&interleaved.data[..8], &bytes[..8]);
+    assert_eq!(&interleaved.data[8..], &[12, 13, 14, 15, 8, 9, 10, 11]);
+    assert_eq!(interleaved.deinterleave().unwrap().data, bytes);
+}
+
+#[test]
+fn rgba32_interleave_swaps_double_words() {
+    let bytes: Vec<u8> = (0..32).collect();
+    let image = NativeImage::read(bytes.as_slice(), ImageFormat::RGBA32, 4, 2).unwrap();
+
+    let interleaved = image.interleave().unwrap();
+
+    assert_eq!(&interleaved.data[16..24], &bytes[24..32]);
+    assert_eq!(&interleaved.data[24..32], &bytes[16..24]);
+
+    let image = NativeImage::read(&bytes[..16], ImageFormat::RGBA32, 2, 2).unwrap();
+    assert!(matches!(
+        image.interleave(),
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+#[test]
+fn yuv16_decodes_with_default_coefficients() {
+    // white and black sharing neutral chroma, then a saturated red pair
+    let image = NativeImage {
+        format: ImageFormat::YUV16,
+        width: 2,
+        height: 2,
+        data: vec![0x80, 235, 0x80, 16, 90, 82, 240, 82],
+    };
+
+    let output = image.decode(None, 0).unwrap();
+
+    assert_eq!(
+        &output[0..8],
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF]
+    );
+    assert!(output[8] > 0xF0 && output[9] < 0x10 && output[10] < 0x10);
+    assert_eq!(&output[8..12], &output[12..16]);
+}
+
+#[test]
+fn yuv16_decodes_with_custom_coefficients() {
+    // with no chroma and no range expansion the output is just Y
+    let coefficients = YUVCoefficients {
+        k0: 0,
+        k1: 0,
+        k2: 0,
+        k3: 0,
+        k4: 0,
+        k5: 0,
+    };
+    let image = NativeImage {
+        format: ImageFormat::YUV16,
+        width: 2,
+        height: 1,
+        data: vec![0x80, 0x40, 0x80, 0x90],
+    };
+
+    let output = image.decode_yuv16(&coefficients).unwrap();
+
+    assert_eq!(output, vec![0x40, 0x40, 0x40, 0xFF, 0x90, 0x90, 0x90, 0xFF]);
+}
+
+#[test]
+fn yuv16_requires_even_width() {
+    let image = NativeImage {
+        format: ImageFormat::YUV16,
+        width: 3,
+        height: 1,
+        data: vec![0; 6],
+    };
+
+    assert!(matches!(
+        image.decode(None, 0),
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+#[test]
+fn png_to_yuv16_round_trip() {
+    let colors = [
+        [0xFF, 0x00, 0x00, 0xFF],
+        [0xFF, 0x00, 0x00, 0xFF],
+        [0x20, 0x80, 0xE0, 0xFF],
+        [0x20, 0x80, 0xE0, 0xFF],
+    ];
+    let image = rgba_png(4, 1, &colors.concat());
+
+    let mut data = Vec::new();
+    image
+        .as_native(&mut data, ImageFormat::YUV16, &EncodeOptions::default())
+        .unwrap();
+    let native = NativeImage::read(data.as_slice(), ImageFormat::YUV16, 4, 1).unwrap();
+    let output = native.decode(None, 0).unwrap();
+
+    for (decoded, expected) in output.chunks_exact(4).zip(colors) {
+        for (channel, value) in decoded.iter().zip(expected) {
+            assert!((*channel as i32 - value as i32).abs() <= 3);
+        }
+    }
 }
 
 // MARK: - Back to Native
@@ -100,7 +386,9 @@ fn png_to_rgba32() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.rgba32");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_rgba32(&mut ouput).unwrap();
+    image
+        .as_rgba32(&mut ouput, &EncodeOptions::default())
+        .unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -113,7 +401,9 @@ fn png_to_rgba16() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.rgba16");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_rgba16(&mut ouput).unwrap();
+    image
+        .as_rgba16(&mut ouput, &EncodeOptions::default())
+        .unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -126,7 +416,7 @@ fn png_to_i4() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.i4");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_i4(&mut ouput).unwrap();
+    image.as_i4(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -139,7 +429,7 @@ fn png_to_i8() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.i8");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_i8(&mut ouput).unwrap();
+    image.as_i8(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -152,7 +442,7 @@ fn png_to_ia4() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.ia4");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_ia4(&mut ouput).unwrap();
+    image.as_ia4(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -165,7 +455,7 @@ fn png_to_ia8() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.ia8");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_ia8(&mut ouput).unwrap();
+    image.as_ia8(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -178,7 +468,9 @@ fn png_to_ia16() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.ia16");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_ia16(&mut ouput).unwrap();
+    image
+        .as_ia16(&mut ouput, &EncodeOptions::default())
+        .unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -191,7 +483,7 @@ fn png_to_ci4() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.ci4");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_ci4(&mut ouput).unwrap();
+    image.as_ci4(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
@@ -204,7 +496,972 @@ fn png_to_ci8() {
     // compare to original
     let original_bytes: &[u8] = include_bytes!("n64/test.ci8");
     let mut ouput: Vec<u8> = Vec::new();
-    image.as_ci8(&mut ouput).unwrap();
+    image.as_ci8(&mut ouput, &EncodeOptions::default()).unwrap();
 
     assert_eq!(ouput, original_bytes);
 }
+
+// MARK: - TLUT extraction
+
+// encodes an indexed png whose palette has the given number of entries
+fn indexed_png(entries: usize) -> Vec<u8> {
+    let palette: Vec<u8> = (0..entries).flat_map(|_| [0xFF, 0x00, 0x80]).collect();
+    encode_png(
+        4,
+        1,
+        ColorType::Indexed,
+        BitDepth::Eight,
+        Some(&palette),
+        Some(&[0x00]),
+        &[0, 1, 2, 3],
+    )
+}
+
+#[test]
+fn png_to_tlut() {
+    let bytes = indexed_png(4);
+    let image = PNGImage::read(bytes.as_slice()).unwrap();
+
+    let tlut = image
+        .as_tlut(
+            ImageFormat::CI4,
+            TLUTType::RGBA16,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+
+    assert_eq!(tlut.entries(), 4);
+    assert_eq!(&tlut.data[..4], &[0xF8, 0x20, 0xF8, 0x21]);
+}
+
+#[test]
+fn png_to_ia16_tlut() {
+    let bytes = indexed_png(4);
+    let image = PNGImage::read(bytes.as_slice()).unwrap();
+
+    let tlut = image
+        .as_tlut(ImageFormat::CI4, TLUTType::IA16, &EncodeOptions::default())
+        .unwrap();
+
+    // intensity of (255, 0, 128) followed by the tRNS alpha
+    assert_eq!(&tlut.data[..4], &[0x3F, 0x00, 0x3F, 0xFF]);
+}
+
+#[test]
+fn ci4_with_ia16_tlut_decodes_grayscale() {
+    let bytes: &[u8] = &[0x01, 0x10];
+    let image = NativeImage::read(bytes, ImageFormat::CI4, 2, 2).unwrap();
+    let palette: &[u8] = &[0x80, 0xFF, 0x20, 0x40];
+    let tlut = TLUT::read(palette, TLUTType::IA16).unwrap();
+
+    let decoded = image.decode(Some(&tlut), 0).unwrap();
+
+    assert_eq!(
+        decoded,
+        vec![
+            0x80, 0x80, 0x80, 0xFF, 0x20, 0x20, 0x20, 0x40, //
+            0x20, 0x20, 0x20, 0x40, 0x80, 0x80, 0x80, 0xFF,
+        ]
+    );
+}
+
+#[test]
+fn tlut_palette_banks() {
+    let tlut = grayscale_tlut(256);
+
+    let bank = tlut.palette(ImageFormat::CI4, 2).unwrap();
+    let whole = tlut.palette(ImageFormat::CI8, 2).unwrap();
+
+    assert_eq!(TLUT::bank_offset(2).unwrap(), 32);
+    assert_eq!(bank.len(), 16 * 4);
+    assert_eq!(bank, tlut.decode().unwrap()[32 * 4..48 * 4]);
+    assert_eq!(whole.len(), 256 * 4);
+    assert!(TLUT::bank_offset(16).is_err());
+    assert!(grayscale_tlut(16).palette(ImageFormat::CI4, 1).is_err());
+}
+
+#[test]
+fn ci4_decodes_with_palette_bank() {
+    let bytes: &[u8] = &[0x0F];
+    let image = NativeImage::read(bytes, ImageFormat::CI4, 2, 1).unwrap();
+    let tlut = grayscale_tlut(256);
+    let colors = tlut.decode().unwrap();
+
+    let decoded = image.decode(Some(&tlut), 3).unwrap();
+
+    assert_eq!(&decoded[..4], &colors[48 * 4..49 * 4]);
+    assert_eq!(&decoded[4..], &colors[63 * 4..64 * 4]);
+    assert!(image.decode(Some(&tlut), 16).is_err());
+}
+
+#[test]
+fn partial_tlut_rejects_out_of_range_indices() {
+    let bytes: &[u8] = &[0x12, 0x13];
+    let tlut = grayscale_tlut(3);
+
+    let image = NativeImage::read(&bytes[..1], ImageFormat::CI4, 2, 1).unwrap();
+    assert!(image.decode(Some(&tlut), 0).is_ok());
+
+    let image = NativeImage::read(&bytes[1..], ImageFormat::CI4, 2, 1).unwrap();
+    assert!(image.decode(Some(&tlut), 0).is_err());
+    assert!(image.as_png(&mut Vec::new(), Some(&tlut), 0).is_err());
+}
+
+#[test]
+fn tlut_rejects_odd_sized_data() {
+    let bytes: &[u8] = &[0x00, 0x12, 0x34];
+    assert!(TLUT::read(bytes, TLUTType::RGBA16).is_err());
+}
+
+#[test]
+fn png_to_tlut_rejects_oversized_palette() {
+    let bytes = indexed_png(17);
+    let image = PNGImage::read(bytes.as_slice()).unwrap();
+
+    assert!(image
+        .as_tlut(
+            ImageFormat::CI4,
+            TLUTType::RGBA16,
+            &EncodeOptions::default()
+        )
+        .is_err());
+    assert!(image
+        .as_tlut(
+            ImageFormat::CI8,
+            TLUTType::RGBA16,
+            &EncodeOptions::default()
+        )
+        .is_ok());
+}
+
+// MARK: - Quantization
+
+#[test]
+fn rgba_png_to_ci4_quantizes_gradient() {
+    // a 32x8 gradient with 256 distinct colors and a transparent first column
+    let data: Vec<u8> = (0..8u8)
+        .flat_map(|y| {
+            (0..32u8).flat_map(move |x| [x * 8, y * 32, 255 - x * 8, if x == 0 { 0 } else { 255 }])
+        })
+        .collect();
+    let image = rgba_png(32, 8, &data);
+
+    let (indices, tlut) = image
+        .quantize(
+            ImageFormat::CI4,
+            TLUTType::RGBA16,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+    let palette = tlut.decode().unwrap();
+
+    assert_eq!(indices.len(), 32 * 8);
+    assert!(indices.iter().all(|index| *index < 16));
+    for (pixel, index) in data.chunks_exact(4).zip(&indices) {
+        let alpha = palette[*index as usize * 4 + 3];
+        assert_eq!(alpha, pixel[3]);
+    }
+
+    let mut output: Vec<u8> = Vec::new();
+    image
+        .as_ci4(&mut output, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(output.len(), 32 * 8 / 2);
+}
+
+#[test]
+fn rgba_png_to_ci8_keeps_exact_colors() {
+    let data: &[u8] = &[
+        248, 0, 0, 255, 0, 248, 0, 255, 0, 0, 248, 255, 248, 0, 0, 255,
+    ];
+    let image = rgba_png(2, 2, data);
+
+    let mut output: Vec<u8> = Vec::new();
+    image
+        .as_ci8(&mut output, &EncodeOptions::default())
+        .unwrap();
+    let tlut = image
+        .as_tlut(
+            ImageFormat::CI8,
+            TLUTType::RGBA16,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+
+    assert_eq!(output[0], output[3]);
+    assert_eq!(&tlut.data[..6], &[0x00, 0x3F, 0x07, 0xC1, 0xF8, 0x01]);
+}
+
+// MARK: - TMEM
+
+#[test]
+fn tmem_reads_split_rgba32() {
+    let mut data = vec![0u8; TMEM::SIZE];
+    // row 0 of a 2x2 tile at address 0 with a line of one word
+    data[0..4].copy_from_slice(&[0x10, 0x11, 0x20, 0x21]);
+    data[0x800..0x804].copy_from_slice(&[0x12, 0x13, 0x22, 0x23]);
+    // row 1 starts at the next word with its 32-bit halves swapped
+    data[12..16].copy_from_slice(&[0x30, 0x31, 0x40, 0x41]);
+    data[0x80C..0x810].copy_from_slice(&[0x32, 0x33, 0x42, 0x43]);
+    let tmem = TMEM::read(data.as_slice()).unwrap();
+
+    let image = tmem.read_image(ImageFormat::RGBA32, 2, 2, 1, 0).unwrap();
+
+    assert_eq!(
+        image.data,
+        vec![
+            0x10, 0x11, 0x12, 0x13, 0x20, 0x21, 0x22, 0x23, //
+            0x30, 0x31, 0x32, 0x33, 0x40, 0x41, 0x42, 0x43,
+        ]
+    );
+}
+
+#[test]
+fn tmem_reads_ci4_tile_and_tlut() {
+    let mut data = vec![0u8; TMEM::SIZE];
+    // a 4x2 CI4 tile at address 2 with a line of one word
+    data[16..18].copy_from_slice(&[0x01, 0x23]);
+    data[28..30].copy_from_slice(&[0x45, 0x67]);
+    for entry in 0..16u8 {
+        let address = TMEM::HIGH_HALF + entry as usize * 8;
+        for lane in 0..4 {
+            data[address + lane * 2 + 1] = entry;
+        }
+    }
+    let tmem = TMEM::read(data.as_slice()).unwrap();
+
+    let image = tmem.read_image(ImageFormat::CI4, 4, 2, 1, 2).unwrap();
+    let tlut = tmem.read_tlut(16, TLUTType::RGBA16).unwrap();
+
+    assert_eq!(image.data, vec![0x01, 0x23, 0x45, 0x67]);
+    assert_eq!(tlut.entries(), 16);
+    assert_eq!(&tlut.data[..6], &[0x00, 0x00, 0x00, 0x01, 0x00, 0x02]);
+}
+
+#[test]
+fn tmem_reads_split_yuv16() {
+    let mut data = vec![0u8; TMEM::SIZE];
+    data[0..2].copy_from_slice(&[0x10, 0x20]);
+    data[0x800..0x802].copy_from_slice(&[0x30, 0x40]);
+    let tmem = TMEM::read(data.as_slice()).unwrap();
+
+    let image = tmem.read_image(ImageFormat::YUV16, 2, 1, 1, 0).unwrap();
+
+    assert_eq!(image.data, vec![0x10, 0x30, 0x20, 0x40]);
+}
+
+#[test]
+fn tmem_requires_full_snapshot() {
+    let data = vec![0u8; TMEM::SIZE - 1];
+    assert!(matches!(
+        TMEM::read(data.as_slice()),
+        Err(Error::TruncatedData { .. })
+    ));
+}
+
+#[test]
+fn tmem_budget_pads_rows_to_words() {
+    let budget = TMEM::budget(ImageFormat::I4, 5, 2).unwrap();
+
+    assert_eq!(budget.line, 1);
+    assert_eq!(budget.bytes, 16);
+}
+
+#[test]
+fn tmem_budget_ci_textures_fit_with_tlut() {
+    let budget = TMEM::budget(ImageFormat::CI8, 64, 32).unwrap();
+
+    assert_eq!(budget.bytes, 2048);
+    assert!(budget.with_tlut.fits);
+    assert_eq!(budget.with_tlut.max_width, 64);
+    assert_eq!(budget.with_tlut.max_height, 32);
+
+    let budget = TMEM::budget(ImageFormat::CI8, 64, 33).unwrap();
+    assert!(!budget.with_tlut.fits);
+    assert!(budget.without_tlut.fits);
+    assert_eq!(budget.with_tlut.max_width, 56);
+}
+
+#[test]
+fn tmem_budget_rgba32_uses_both_halves() {
+    let budget = TMEM::budget(ImageFormat::RGBA32, 32, 32).unwrap();
+
+    assert_eq!(budget.line, 8);
+    assert_eq!(budget.bytes, 4096);
+    assert!(budget.without_tlut.fits);
+    assert!(!budget.with_tlut.fits);
+    assert_eq!(budget.without_tlut.max_height, 32);
+}
+
+#[test]
+fn tmem_budget_rejects_empty_textures() {
+    assert!(matches!(
+        TMEM::budget(ImageFormat::RGBA16, 0, 16),
+        Err(Error::DimensionMismatch { .. })
+    ));
+}
+
+// MARK: - Tiles
+
+fn i8_ramp_tile(cms: u8, masks: u8, shifts: u8) -> (TMEM, TileDescriptor) {
+    let mut data = vec![0u8; TMEM::SIZE];
+    data[0..4].copy_from_slice(&[0x00, 0x40, 0x80, 0xC0]);
+
+    let tile = TileDescriptor {
+        format: ImageFormat::I8,
+        line: 1,
+        tmem: 0,
+        palette: 0,
+        cms,
+        cmt: TileDescriptor::CLAMP,
+        masks,
+        maskt: 0,
+        shifts,
+        shiftt: 0,
+        sl: 0,
+        tl: 0,
+        sh: 3 << 2,
+        th: 0,
+    };
+
+    (TMEM::read(data.as_slice()).unwrap(), tile)
+}
+
+fn intensities(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4).map(|pixel| pixel[0]).collect()
+}
+
+#[test]
+fn tile_wraps_with_mask() {
+    let (tmem, tile) = i8_ramp_tile(TileDescriptor::WRAP, 2, 0);
+    let output = tile.render(&tmem, TLUTType::RGBA16, 0, 0, 8, 1).unwrap();
+
+    assert_eq!(
+        intensities(&output),
+        vec![0x00, 0x40, 0x80, 0xC0, 0x00, 0x40, 0x80, 0xC0]
+    );
+}
+
+#[test]
+fn tile_mirrors_with_mask() {
+    let (tmem, tile) = i8_ramp_tile(TileDescriptor::MIRROR, 2, 0);
+    let output = tile.render(&tmem, TLUTType::RGBA16, -2, 0, 10, 1).unwrap();
+
+    assert_eq!(
+        intensities(&output),
+        vec![0x40, 0x00, 0x00, 0x40, 0x80, 0xC0, 0xC0, 0x80, 0x40, 0x00]
+    );
+}
+
+#[test]
+fn tile_clamps_without_mask() {
+    let (tmem, tile) = i8_ramp_tile(TileDescriptor::WRAP, 0, 0);
+    let output = tile.render(&tmem, TLUTType::RGBA16, -2, 0, 8, 2).unwrap();
+
+    assert_eq!(
+        intensities(&output[..32]),
+        vec![0x00, 0x00, 0x00, 0x40, 0x80, 0xC0, 0xC0, 0xC0]
+    );
+    assert_eq!(&output[..32], &output[32..]);
+}
+
+#[test]
+fn tile_applies_shift() {
+    let (tmem, tile) = i8_ramp_tile(TileDescriptor::WRAP, 2, 1);
+    let output = tile.render(&tmem, TLUTType::RGBA16, 0, 0, 8, 1).unwrap();
+
+    assert_eq!(
+        intensities(&output),
+        vec![0x00, 0x00, 0x40, 0x40, 0x80, 0x80, 0xC0, 0xC0]
+    );
+}
+
+#[test]
+fn tile_renders_ci4_with_tlut_from_tmem() {
+    let mut data = vec![0u8; TMEM::SIZE];
+    data[0] = 0x12;
+    // bank 1 starts at entry 16
+    for (entry, color) in [(17usize, 0xF801u16), (18, 0x07C1)] {
+        let address = TMEM::HIGH_HALF + entry * 8;
+        for lane in 0..4 {
+            data[address + lane * 2..address + lane * 2 + 2].copy_from_slice(&color.to_be_bytes());
+        }
+    }
+    let tmem = TMEM::read(data.as_slice()).unwrap();
+
+    let tile = TileDescriptor {
+        format: ImageFormat::CI4,
+        line: 1,
+        tmem: 0,
+        palette: 1,
+        cms: TileDescriptor::WRAP,
+        cmt: TileDescriptor::WRAP,
+        masks: 1,
+        maskt: 0,
+        shifts: 0,
+        shiftt: 0,
+        sl: 0,
+        tl: 0,
+        sh: 1 << 2,
+        th: 0,
+    };
+    let output = tile.render(&tmem, TLUTType::RGBA16, 0, 0, 3, 1).unwrap();
+
+    assert_eq!(&output[0..4], &[0xFF, 0x00, 0x00, 0xFF]);
+    assert_eq!(&output[4..8], &[0x00, 0xFF, 0x00, 0xFF]);
+    assert_eq!(&output[8..12], &output[0..4]);
+}
+
+// MARK: - Mipmaps
+
+#[test]
+fn mipmap_box_filters_and_pads_levels() {
+    // a 4x2 image whose left half is black and right half white
+    let mut data = Vec::new();
+    for _ in 0..2 {
+        for x in 0..4 {
+            let value = if x < 2 { 0x00 } else { 0xFF };
+            data.extend_from_slice(&[value, value, value, 0xFF]);
+        }
+    }
+    let image = rgba_png(4, 2, &data);
+
+    let mipmap = Mipmap::from_png(
+        &image,
+        ImageFormat::RGBA32,
+        TLUTType::RGBA16,
+        8,
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+
+    let sizes: Vec<(u32, u32, u16, u16)> = mipmap
+        .levels
+        .iter()
+        .map(|level| (level.width, level.height, level.line, level.tmem))
+        .collect();
+    assert_eq!(sizes, vec![(4, 2, 1, 0), (2, 1, 1, 2), (1, 1, 1, 3)]);
+
+    // 2x1 rows of RGBA32 get padded out to a full word in each half
+    assert_eq!(mipmap.levels[1].data.len(), 16);
+    assert_eq!(
+        &mipmap.levels[1].data[..8],
+        &[0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+    assert_eq!(&mipmap.levels[2].data[..4], &[0x80, 0x80, 0x80, 0xFF]);
+    assert!(mipmap.tlut.is_none());
+}
+
+#[test]
+fn mipmap_limits_levels() {
+    let image = rgba_png(8, 8, &[0x40; 8 * 8 * 4]);
+
+    let mipmap = Mipmap::from_png(
+        &image,
+        ImageFormat::RGBA16,
+        TLUTType::RGBA16,
+        2,
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(mipmap.levels.len(), 2);
+    assert_eq!(mipmap.data().len(), 8 * 16 + 4 * 8);
+}
+
+#[test]
+fn mipmap_yuv16_chain_stops_at_even_widths() {
+    let image = rgba_png(8, 8, &[0x40; 8 * 8 * 4]);
+
+    let mipmap = Mipmap::from_png(
+        &image,
+        ImageFormat::YUV16,
+        TLUTType::RGBA16,
+        Mipmap::MAX_LEVELS,
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+
+    let widths: Vec<u32> = mipmap.levels.iter().map(|level| level.width).collect();
+    assert_eq!(widths, [8, 4, 2]);
+}
+
+#[test]
+fn mipmap_ci_levels_share_a_tlut() {
+    let mut data = Vec::new();
+    for index in 0..16u8 {
+        data.extend_from_slice(&[index * 16, 0x00, 0xFF - index * 16, 0xFF]);
+    }
+    let image = rgba_png(4, 4, &data);
+
+    let mipmap = Mipmap::from_png(
+        &image,
+        ImageFormat::CI4,
+        TLUTType::RGBA16,
+        3,
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+    let tlut = mipmap.tlut.as_ref().unwrap();
+    let budget = mipmap.budget();
+
+    assert_eq!(mipmap.levels.len(), 3);
+    assert!(tlut.entries() <= 16);
+    assert_eq!(budget.available, TMEM::HIGH_HALF);
+    assert_eq!(budget.bytes, 4 * 8 + 2 * 8 + 8);
+    assert!(budget.fits);
+
+    for level in &mipmap.levels {
+        let native = NativeImage {
+            format: ImageFormat::CI4,
+            width: level.width,
+            height: level.height,
+            data: level
+                .data
+                .chunks_exact(8)
+                .flat_map(|row| row[..ImageFormat::CI4.bytes_per_row(level.width)].to_vec())
+                .collect(),
+        };
+        assert!(native.decode(Some(tlut), 0).is_ok());
+    }
+}
+
+// MARK: - Dithering
+
+fn dithered(format: ImageFormat, dither: Dither, width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let image = rgba_png(width, height, rgba);
+    let options = EncodeOptions {
+        dither,
+        ..Default::default()
+    };
+
+    let mut output = Vec::new();
+    image.as_native(&mut output, format, &options).unwrap();
+    output
+}
+
+#[test]
+fn rgba16_bayer_dither_uses_magic_square() {
+    // halfway between the first two 5-bit levels
+    let rgba = [0x04, 0x04, 0x04, 0xFF].repeat(16);
+
+    let none = dithered(ImageFormat::RGBA16, Dither::None, 4, 4, &rgba);
+    let bayer = dithered(ImageFormat::RGBA16, Dither::Bayer, 4, 4, &rgba);
+
+    assert!(none.chunks_exact(2).all(|pixel| pixel == [0x00, 0x01]));
+
+    // cells of the magic square that are 4 or more round up
+    let raised: Vec<bool> = bayer
+        .chunks_exact(2)
+        .map(|pixel| pixel == [0x08, 0x43])
+        .collect();
+    assert_eq!(raised.iter().filter(|raised| **raised).count(), 8);
+    assert_eq!(&raised[..4], &[false, true, false, true]);
+}
+
+#[test]
+fn i4_floyd_steinberg_preserves_average() {
+    let rgba = [0x08, 0x08, 0x08, 0xFF].repeat(8 * 8);
+
+    let none = dithered(ImageFormat::I4, Dither::None, 8, 8, &rgba);
+    let diffused = dithered(ImageFormat::I4, Dither::FloydSteinberg, 8, 8, &rgba);
+
+    assert!(none.iter().all(|byte| *byte == 0x00));
+
+    let ones: u32 = diffused
+        .iter()
+        .map(|byte| (byte >> 4) as u32 + (byte & 0x0F) as u32)
+        .sum();
+    assert!((24..=40).contains(&ones));
+}
+
+#[test]
+fn ia8_dithers_alpha_too() {
+    let rgba = [0xFF, 0xFF, 0xFF, 0x08].repeat(8 * 8);
+
+    let none = dithered(ImageFormat::IA8, Dither::None, 8, 8, &rgba);
+    let diffused = dithered(ImageFormat::IA8, Dither::FloydSteinberg, 8, 8, &rgba);
+
+    assert!(none.iter().all(|byte| *byte == 0xF0));
+    assert!(diffused.iter().all(|byte| byte >> 4 == 0x0F));
+
+    let alpha: u32 = diffused.iter().map(|byte| (byte & 0x0F) as u32).sum();
+    assert!((24..=40).contains(&alpha));
+}
+
+#[test]
+fn ia8_keeps_intensity_and_alpha_apart() {
+    let png = encode_png(
+        1,
+        1,
+        ColorType::GrayscaleAlpha,
+        BitDepth::Eight,
+        None,
+        None,
+        &[0x5A, 0x80],
+    );
+    let image = PNGImage::read(png.as_slice()).unwrap();
+
+    let mut output = Vec::new();
+    image
+        .as_ia8(&mut output, &EncodeOptions::default())
+        .unwrap();
+
+    assert_eq!(output, vec![0x58]);
+}
+
+#[test]
+fn ci4_dithering_keeps_the_tlut() {
+    let rgba: Vec<u8> = (0..=255u8)
+        .flat_map(|value| [value, value / 2, 255 - value, 0xFF])
+        .collect();
+    let image = rgba_png(16, 16, &rgba);
+
+    let (plain, plain_tlut) = image
+        .quantize(
+            ImageFormat::CI4,
+            TLUTType::RGBA16,
+            &EncodeOptions::default(),
+        )
+        .unwrap();
+    let (diffused, diffused_tlut) = image
+        .quantize(
+            ImageFormat::CI4,
+            TLUTType::RGBA16,
+            &EncodeOptions {
+                dither: Dither::FloydSteinberg,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(plain_tlut.data, diffused_tlut.data);
+    assert_ne!(plain, diffused);
+    assert!(diffused
+        .iter()
+        .all(|index| (*index as usize) < plain_tlut.entries()));
+}
+
+// MARK: - Alpha
+
+#[test]
+fn alpha_threshold_picks_opaque_pixels() {
+    let data: &[u8] = &[255, 255, 255, 200, 255, 255, 255, 100];
+    let image = rgba_png(2, 1, data);
+
+    let mut output: Vec<u8> = Vec::new();
+    image
+        .as_rgba16(&mut output, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(output, [0xFF, 0xFF, 0xFF, 0xFE]);
+
+    let options = EncodeOptions {
+        alpha_threshold: 64,
+        ..Default::default()
+    };
+    let mut output: Vec<u8> = Vec::new();
+    image.as_rgba16(&mut output, &options).unwrap();
+    assert_eq!(output, [0xFF, 0xFF, 0xFF, 0xFF]);
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_ia4(&mut output, &options).unwrap();
+    assert_eq!(output, [0xFF]);
+}
+
+#[test]
+fn premultiply_scales_colors_by_alpha() {
+    let data: &[u8] = &[255, 128, 0, 128, 255, 255, 255, 255];
+    let image = rgba_png(2, 1, data);
+    let options = EncodeOptions {
+        premultiply: true,
+        ..Default::default()
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_rgba32(&mut output, &options).unwrap();
+    assert_eq!(output, [128, 64, 0, 128, 255, 255, 255, 255]);
+}
+
+#[test]
+fn bleed_fills_transparent_pixels_from_neighbours() {
+    // an opaque red pixel in the corner of a transparent black 3x1 strip
+    let data: &[u8] = &[255, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+    let image = rgba_png(3, 1, data);
+    let options = EncodeOptions {
+        bleed: true,
+        ..Default::default()
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_rgba32(&mut output, &options).unwrap();
+    assert_eq!(output, [255, 0, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0]);
+
+    // pixels under the threshold get bled too when the format drops them
+    let data: &[u8] = &[0, 255, 0, 255, 0, 0, 0, 64];
+    let image = rgba_png(2, 1, data);
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_rgba16(&mut output, &options).unwrap();
+    assert_eq!(output, [0x07, 0xC1, 0x07, 0xC0]);
+}
+
+#[test]
+fn bleed_without_opaque_pixels_keeps_colors() {
+    let data: &[u8] = &[10, 20, 30, 0, 40, 50, 60, 0];
+    let image = rgba_png(2, 1, data);
+    let options = EncodeOptions {
+        bleed: true,
+        ..Default::default()
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_rgba32(&mut output, &options).unwrap();
+    assert_eq!(output, data);
+}
+
+// MARK: - PNG inputs
+
+#[test]
+fn low_bit_depth_grayscale_is_expanded() {
+    let bytes = encode_png(
+        4,
+        1,
+        ColorType::Grayscale,
+        BitDepth::Two,
+        None,
+        None,
+        &[0b00011011],
+    );
+    let image = PNGImage::read(bytes.as_slice()).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_i8(&mut output, &EncodeOptions::default()).unwrap();
+    assert_eq!(output, [0, 85, 170, 255]);
+}
+
+#[test]
+fn sixteen_bit_rgba_is_reduced() {
+    let data: &[u8] = &[0xFF, 0xFF, 0x80, 0x00, 0x00, 0xFF, 0x12, 0x34];
+    let bytes = encode_png(1, 1, ColorType::Rgba, BitDepth::Sixteen, None, None, data);
+    let image = PNGImage::read(bytes.as_slice()).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    image
+        .as_rgba32(&mut output, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(output, [0xFF, 0x80, 0x00, 0x12]);
+}
+
+#[test]
+fn rgb_trns_becomes_alpha() {
+    // tRNS holds a 16-bit sample per channel, marking magenta as transparent
+    let png = encode_png(
+        2,
+        1,
+        ColorType::Rgb,
+        BitDepth::Eight,
+        None,
+        Some(&[0, 0xFF, 0, 0, 0, 0xFF]),
+        &[0xFF, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+    );
+
+    let image = PNGImage::read(png.as_slice()).unwrap();
+    let mut output: Vec<u8> = Vec::new();
+    image
+        .as_rgba32(&mut output, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(output, [0xFF, 0, 0xFF, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn low_bit_depth_indexed_keeps_indices() {
+    let png = encode_png(
+        3,
+        1,
+        ColorType::Indexed,
+        BitDepth::One,
+        Some(&[0, 0, 0, 0xFF, 0xFF, 0xFF]),
+        None,
+        &[0b10100000],
+    );
+
+    let image = PNGImage::read(png.as_slice()).unwrap();
+    let mut indices: Vec<u8> = Vec::new();
+    image
+        .as_ci8(&mut indices, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(indices, [1, 0, 1]);
+
+    let mut intensities: Vec<u8> = Vec::new();
+    image
+        .as_i8(&mut intensities, &EncodeOptions::default())
+        .unwrap();
+    assert_eq!(intensities, [0xFF, 0, 0xFF]);
+}
+
+#[test]
+fn every_png_input_converts_to_every_format() {
+    let inputs = [
+        encode_png(
+            2,
+            2,
+            ColorType::Grayscale,
+            BitDepth::One,
+            None,
+            None,
+            &[0x80, 0x40],
+        ),
+        encode_png(
+            2,
+            2,
+            ColorType::Grayscale,
+            BitDepth::Sixteen,
+            None,
+            None,
+            &[0x12; 8],
+        ),
+        encode_png(
+            2,
+            2,
+            ColorType::GrayscaleAlpha,
+            BitDepth::Eight,
+            None,
+            None,
+            &[0x80; 8],
+        ),
+        encode_png(
+            2,
+            2,
+            ColorType::Rgb,
+            BitDepth::Eight,
+            None,
+            None,
+            &[0x40; 12],
+        ),
+        encode_png(
+            2,
+            2,
+            ColorType::Rgba,
+            BitDepth::Sixteen,
+            None,
+            None,
+            &[0xC0; 32],
+        ),
+        indexed_png(4),
+    ];
+
+    for bytes in inputs {
+        let image = PNGImage::read(bytes.as_slice()).unwrap();
+        for format in ImageFormat::value_variants() {
+            let mut output: Vec<u8> = Vec::new();
+            image
+                .as_native(&mut output, *format, &EncodeOptions::default())
+                .unwrap();
+            let expected = format.bytes_for(image.width(), image.height());
+            assert_eq!(output.len(), expected, "{:?}", format);
+        }
+    }
+}
+
+// MARK: - Intensity
+
+#[test]
+fn intensity_modes_reduce_colors() {
+    let data: &[u8] = &[200, 100, 50, 128];
+    let image = rgba_png(1, 1, data);
+
+    let expected = [
+        (IntensityMode::Rec601, 124),
+        (IntensityMode::Rec709, 118),
+        (IntensityMode::Average, 117),
+        (IntensityMode::Max, 200),
+        (IntensityMode::Red, 200),
+        (IntensityMode::Green, 100),
+        (IntensityMode::Blue, 50),
+        (IntensityMode::Alpha, 128),
+    ];
+
+    for (intensity, value) in expected {
+        let options = EncodeOptions {
+            intensity,
+            ..Default::default()
+        };
+
+        let mut output: Vec<u8> = Vec::new();
+        image.as_i8(&mut output, &options).unwrap();
+        assert_eq!(output, [value], "{:?}", intensity);
+    }
+}
+
+#[test]
+fn alpha_intensity_keeps_white_on_transparent_fonts() {
+    // a glyph edge authored as white fading out over black transparent pixels
+    let data: &[u8] = &[255, 255, 255, 255, 0, 0, 0, 136];
+    let image = rgba_png(2, 1, data);
+    let options = EncodeOptions {
+        intensity: IntensityMode::Alpha,
+        ..Default::default()
+    };
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_i4(&mut output, &options).unwrap();
+    assert_eq!(output, [0xF8]);
+
+    let tlut = image
+        .as_tlut(ImageFormat::CI4, TLUTType::IA16, &options)
+        .unwrap();
+    assert!(tlut.data.chunks_exact(2).all(|entry| entry[0] == entry[1]));
+}
+
+// MARK: - Round trips
+
+#[test]
+fn expanded_channels_reach_full_range() {
+    let rgba16 = NativeImage::read(&[0xFF, 0xFF][..], ImageFormat::RGBA16, 1, 1).unwrap();
+    let ia4 = NativeImage::read(&[0xE0][..], ImageFormat::IA4, 1, 1).unwrap();
+    let ia8 = NativeImage::read(&[0xF8][..], ImageFormat::IA8, 1, 1).unwrap();
+
+    assert_eq!(rgba16.decode(None, 0).unwrap(), vec![0xFF; 4]);
+    assert_eq!(ia4.decode(None, 0).unwrap(), vec![0xFF, 0xFF, 0xFF, 0x00]);
+    assert_eq!(ia8.decode(None, 0).unwrap(), vec![0xFF, 0xFF, 0xFF, 0x88]);
+}
+
+#[test]
+fn native_to_png_to_native_is_lossless() {
+    let (width, height) = (8, 4);
+
+    for (seed, format) in ImageFormat::value_variants().iter().enumerate() {
+        let tlut_types: &[Option<TLUTType>] = match format {
+            ImageFormat::CI4 | ImageFormat::CI8 => &[Some(TLUTType::RGBA16), Some(TLUTType::IA16)],
+            // converting to RGB clamps YUV values outside the RGB gamut and rounds through
+            // the K0-K5 coefficients, so YUV16 drifts on every trip instead of settling
+            ImageFormat::YUV16 => continue,
+            _ => &[None],
+        };
+
+        for tlut_type in tlut_types {
+            let data = noise(format.bytes_for(width, height), seed as u32 + 1);
+            let image = NativeImage::read(data.as_slice(), *format, width, height).unwrap();
+            let tlut = tlut_type.map(|tlut_type| {
+                let entries = format.tlut_entries().unwrap();
+                TLUT::read(noise(entries * 2, seed as u32 + 100).as_slice(), tlut_type).unwrap()
+            });
+
+            let mut png = Vec::new();
+            image.as_png(&mut png, tlut.as_ref(), 0).unwrap();
+            let png = PNGImage::read(png.as_slice()).unwrap();
+
+            let mut native = Vec::new();
+            png.as_native(&mut native, *format, &EncodeOptions::default())
+                .unwrap();
+            assert_eq!(native, data, "{:?} {:?}", format, tlut_type);
+
+            if let Some(tlut) = tlut {
+                let output = png
+                    .as_tlut(*format, tlut.tlut_type, &EncodeOptions::default())
+                    .unwrap();
+                assert_eq!(output.data, tlut.data, "{:?} {:?}", format, tlut_type);
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+mod common;
+
+use common::rgba_png;
+use farbe::image::n64::{EncodeOptions, ImageFormat, TLUTType};
+use farbe::rom::byte_order::ByteOrder;
+use farbe::rom::crc::{self, Cic};
+use farbe::rom::{Rom, TextureSlot};
+use farbe::Error;
+
+// a tiny rom with a big-endian header, a 4x2 RGBA16 texture at 0x40 and
+// a 16 entry TLUT at 0x50 followed by a 4x2 CI4 texture at 0x70
+fn z64() -> Vec<u8> {
+    let mut data = vec![0x80, 0x37, 0x12, 0x40];
+    data.resize(0x40, 0);
+    data.extend((0..8u16).flat_map(|i| (i << 11 | 1).to_be_bytes()));
+    data.extend((0..16u16).flat_map(|i| (i << 1 | 1).to_be_bytes()));
+    data.extend([0x01, 0x23, 0x45, 0x67]);
+    data.resize(0x80, 0);
+    data
+}
+
+fn v64() -> Vec<u8> {
+    z64()
+        .chunks_exact(2)
+        .flat_map(|half| [half[1], half[0]])
+        .collect()
+}
+
+fn n64() -> Vec<u8> {
+    z64()
+        .chunks_exact(4)
+        .flat_map(|word| [word[3], word[2], word[1], word[0]])
+        .collect()
+}
+
+#[test]
+fn roms_are_read_in_big_endian_order() {
+    for data in [z64(), v64(), n64()] {
+        let rom = Rom::read(data.as_slice()).unwrap();
+        assert_eq!(rom.data, z64());
+    }
+}
+
+#[test]
+fn byte_order_is_detected_from_the_header() {
+    assert_eq!(ByteOrder::detect(&z64()).unwrap(), ByteOrder::Z64);
+    assert_eq!(ByteOrder::detect(&v64()).unwrap(), ByteOrder::V64);
+    assert_eq!(ByteOrder::detect(&n64()).unwrap(), ByteOrder::N64);
+
+    let rom = Rom::read(n64().as_slice()).unwrap();
+    assert_eq!(rom.byte_order, ByteOrder::N64);
+}
+
+#[test]
+fn byte_orders_convert_into_each_other() {
+    let roms = [
+        (ByteOrder::Z64, z64()),
+        (ByteOrder::V64, v64()),
+        (ByteOrder::N64, n64()),
+    ];
+
+    for (from, data) in &roms {
+        for (to, expected) in &roms {
+            let mut converted = data.clone();
+            from.convert(&mut converted, *to);
+            assert_eq!(&converted, expected, "{:?} -> {:?}", from, to);
+        }
+    }
+}
+
+#[test]
+fn roms_are_written_in_any_byte_order() {
+    let rom = Rom::read(z64().as_slice()).unwrap();
+
+    let mut output: Vec<u8> = Vec::new();
+    rom.write(&mut output, ByteOrder::V64).unwrap();
+    assert_eq!(output, v64());
+}
+
+#[test]
+fn unknown_byte_order_is_rejected() {
+    let data: &[u8] = &[0x80, 0x12, 0x37, 0x40];
+    assert!(matches!(
+        Rom::read(data),
+        Err(Error::UnknownByteOrder([0x80, 0x12, 0x37, 0x40]))
+    ));
+    assert!(matches!(
+        ByteOrder::detect(&[0x80]),
+        Err(Error::TruncatedData {
+            expected: 4,
+            actual: 1
+        })
+    ));
+}
+
+#[test]
+fn texture_is_extracted_by_offset() {
+    let rom = Rom::read(v64().as_slice()).unwrap();
+    let image = rom.image(0x40, ImageFormat::RGBA16, 4, 2).unwrap();
+    assert_eq!(image.data, z64()[0x40..0x50]);
+
+    let mut output: Vec<u8> = Vec::new();
+    image.as_png(&mut output, None, 0).unwrap();
+}
+
+#[test]
+fn color_indexed_texture_is_extracted_with_its_tlut() {
+    let rom = Rom::read(n64().as_slice()).unwrap();
+    let image = rom.image(0x70, ImageFormat::CI4, 4, 2).unwrap();
+    let tlut = rom.tlut(0x50, ImageFormat::CI4, TLUTType::RGBA16).unwrap();
+    assert_eq!(tlut.entries(), 16);
+
+    let rgba = image.decode(Some(&tlut), 0).unwrap();
+    assert_eq!(&rgba[4..8], &[0, 0, 8, 255]);
+}
+
+#[test]
+fn offsets_past_the_end_are_truncated() {
+    let rom = Rom::read(z64().as_slice()).unwrap();
+    assert!(matches!(
+        rom.image(0x7C, ImageFormat::RGBA16, 4, 2),
+        Err(Error::TruncatedData {
+            expected: 0x8C,
+            actual: 0x80
+        })
+    ));
+    assert!(matches!(
+        rom.tlut(0x50, ImageFormat::I4, TLUTType::RGBA16),
+        Err(Error::NotColorIndexed(ImageFormat::I4))
+    ));
+}
+
+// MARK: - Injection
+
+// a rom big enough for the checksum to cover, filled with a repeating pattern
+fn patterned() -> Vec<u8> {
+    let mut data: Vec<u8> = (0..0x101000usize).map(|i| (i * 7 + 3) as u8).collect();
+    data[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+    data
+}
+
+fn slot(offset: usize, format: ImageFormat, width: u32, height: u32) -> TextureSlot {
+    TextureSlot {
+        offset,
+        format,
+        width,
+        height,
+        tlut: None,
+    }
+}
+
+#[test]
+fn checksum_matches_every_cic() {
+    let data = patterned();
+    let expected = [
+        (Cic::Cic6101, [0xF6C453DE, 0x6D3C908C]),
+        (Cic::Cic6102, [0xF6C453DE, 0x6D3C908C]),
+        (Cic::Cic6103, [0xA592715B, 0x6CC2672E]),
+        (Cic::Cic6105, [0xDD10FA38, 0xC11B9C1E]),
+        (Cic::Cic6106, [0x5141AE92, 0x66E19326]),
+    ];
+
+    for (cic, crcs) in expected {
+        assert_eq!(crc::checksum(&data, cic).unwrap(), crcs, "{:?}", cic);
+    }
+
+    // with nothing but zeroes every step only adds the seed to t1
+    let mut zeroes = vec![0; 0x101000];
+    zeroes[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+    assert_eq!(
+        crc::checksum(&zeroes, Cic::Cic6102).unwrap(),
+        [0xF8CA4DDC, 0x303A4DDC]
+    );
+}
+
+#[test]
+fn update_crc_writes_the_header() {
+    let mut rom = Rom::read(patterned().as_slice()).unwrap();
+    assert!(matches!(rom.update_crc(None), Err(Error::UnknownCic(_))));
+
+    assert_eq!(rom.update_crc(Some(Cic::Cic6105)).unwrap(), Cic::Cic6105);
+    assert_eq!(
+        rom.data[0x10..0x18],
+        [0xDD, 0x10, 0xFA, 0x38, 0xC1, 0x1B, 0x9C, 0x1E]
+    );
+
+    assert!(matches!(
+        Rom::read(z64().as_slice())
+            .unwrap()
+            .update_crc(Some(Cic::Cic6102)),
+        Err(Error::TruncatedData {
+            expected: 0x101000,
+            actual: 0x80
+        })
+    ));
+}
+
+#[test]
+fn png_is_injected_over_a_texture_of_the_same_size() {
+    let data: Vec<u8> = (0..8u8).flat_map(|i| [i * 32, 0, 0, 255]).collect();
+    let image = rgba_png(4, 2, &data);
+
+    let mut rom = Rom::read(v64().as_slice()).unwrap();
+    rom.inject(
+        &slot(0x40, ImageFormat::RGBA16, 4, 2),
+        &image,
+        &EncodeOptions::default(),
+    )
+    .unwrap();
+
+    let expected: Vec<u8> = (0..8u16)
+        .flat_map(|i| ((i * 4) << 11 | 1).to_be_bytes())
+        .collect();
+    assert_eq!(rom.data[0x40..0x50], expected);
+
+    let mut output: Vec<u8> = Vec::new();
+    rom.write(&mut output, rom.byte_order).unwrap();
+    assert_eq!(ByteOrder::detect(&output).unwrap(), ByteOrder::V64);
+}
+
+#[test]
+fn injected_png_has_to_match_the_original_size() {
+    let image = rgba_png(2, 2, &[0; 16]);
+    let mut rom = Rom::read(z64().as_slice()).unwrap();
+
+    assert!(matches!(
+        rom.inject(
+            &slot(0x40, ImageFormat::RGBA16, 4, 2),
+            &image,
+            &EncodeOptions::default()
+        ),
+        Err(Error::DimensionMismatch {
+            width: 4,
+            height: 2
+        })
+    ));
+
+    // the same number of pixels in a different shape would scramble the texture
+    let image = rgba_png(8, 2, &[0; 64]);
+    assert!(matches!(
+        rom.inject(
+            &slot(0x40, ImageFormat::RGBA16, 2, 8),
+            &image,
+            &EncodeOptions::default()
+        ),
+        Err(Error::DimensionMismatch {
+            width: 2,
+            height: 8
+        })
+    ));
+    assert!(matches!(
+        rom.write_at(0x7C, &[0; 8]),
+        Err(Error::TruncatedData {
+            expected: 0x84,
+            actual: 0x80
+        })
+    ));
+}
+
+#[test]
+fn color_indexed_png_is_injected_with_its_tlut() {
+    let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+    let data: Vec<u8> = (0..8).flat_map(|i| colors[i % 3]).collect();
+    let image = rgba_png(4, 2, &data);
+
+    let mut rom = Rom::read(z64().as_slice()).unwrap();
+    let mut slot = slot(0x70, ImageFormat::CI4, 4, 2);
+    assert!(matches!(
+        rom.inject(&slot, &image, &EncodeOptions::default()),
+        Err(Error::MissingTLUT(ImageFormat::CI4))
+    ));
+
+    // neither the texture nor the TLUT is written when the TLUT doesn't fit
+    slot.tlut = Some((0x7C, TLUTType::RGBA16));
+    assert!(matches!(
+        rom.inject(&slot, &image, &EncodeOptions::default()),
+        Err(Error::TruncatedData {
+            expected: 0x82,
+            actual: 0x80
+        })
+    ));
+    assert_eq!(rom.data, z64());
+
+    slot.tlut = Some((0x50, TLUTType::RGBA16));
+    rom.inject(&slot, &image, &EncodeOptions::default())
+        .unwrap();
+
+    let tlut = rom.tlut(0x50, ImageFormat::CI4, TLUTType::RGBA16).unwrap();
+    let texture = rom.image(0x70, ImageFormat::CI4, 4, 2).unwrap();
+    assert_eq!(texture.decode(Some(&tlut), 0).unwrap(), data);
+}